@@ -1,6 +1,7 @@
 #! /usr/bin/env python3
 
 import sys
+from typing import Optional
 from ctypes import *
 
 class GraphSimulation(Structure):
@@ -26,10 +27,16 @@ class Simulation:
 
         # Function Definitions
         lib.create_graph_simulation.restype = GraphSimulation
+
+        lib.create_top_simulation.argtypes = c_void_p,c_ulonglong
+        lib.create_top_simulation.restype = GraphSimulation
         
-        lib.simulate.argtypes = c_void_p,c_ulonglong
+        lib.simulate.argtypes = c_void_p,c_void_p,c_ulonglong
         lib.simulate.restype = c_bool
 
+        lib.report_contention.argtypes = c_void_p,c_void_p
+        lib.report_contention.restype = c_bool
+
         lib.get_value.argtypes = c_void_p,c_void_p,c_void_p,c_ulonglong,POINTER(POINTER(c_bool))
         lib.get_value.restype = c_size_t
         
@@ -41,14 +48,30 @@ class Simulation:
         
         lib.get_width.argtypes = c_void_p,c_void_p,c_ulonglong
         lib.get_width.restype = c_ulonglong
+
+        lib.get_oscillations.argtypes = c_void_p,c_void_p,c_void_p
+        lib.get_oscillations.restype = c_size_t
+
+        lib.set_oscillation_threshold.argtypes = c_void_p,c_ulonglong
         
+        lib.run_source_tests.restype = c_bool
+
+        lib.reload_simulation.argtypes = c_void_p,c_ulonglong,POINTER(GraphSimulation)
+        lib.reload_simulation.restype = c_bool
+
         lib.drop_bools.argtypes = c_void_p,c_size_t
         lib.drop_chars.argtypes = c_void_p,c_size_t
 
-    def __init__(self):
+    def __init__(self, top: Optional[str] = None):
         self._load_lib()
 
-        graph_sim = self._lib.create_graph_simulation()
+        # without a top module the one of rva.toml is used, an empty name stands for it on reload
+        self._top = top or ''
+        if top is None:
+            graph_sim = self._lib.create_graph_simulation()
+        else:
+            top_ptr,top_len = str_to_ptr(top)
+            graph_sim = self._lib.create_top_simulation(top_ptr, top_len)
         self._graph = graph_sim.graph
         self._sim = graph_sim.sim
 
@@ -84,8 +107,22 @@ class Simulation:
 
         return buffer
 
-    def run(self, bound:int = 0):
-        self._lib.simulate(self._sim, c_ulonglong(bound))
+    def run(self, bound:int = 0) -> bool:
+        return self._lib.simulate(self._sim, self._graph, c_ulonglong(bound))
+
+    def reload(self) -> bool:
+        top_ptr,top_len = str_to_ptr(self._top)
+        state = GraphSimulation(self._graph, self._sim)
+        if not self._lib.reload_simulation(top_ptr, top_len, byref(state)):
+            return False
+        self._graph = state.graph
+        self._sim = state.sim
+        if self.autorun_enabled:
+            self.run(self.autorun_bound)
+        return True
+
+    def run_tests(self) -> bool:
+        return self._lib.run_source_tests()
 
     def get_description(self, location: str):
         path_ptr,path_len = str_to_ptr(location)
@@ -99,6 +136,20 @@ class Simulation:
         self._lib.drop_chars(desc_ptr, length)
         return description
 
+    def get_oscillations(self):
+        desc_ptr = POINTER(c_byte)()
+        length = self._lib.get_oscillations(self._sim, self._graph, byref(desc_ptr))
+
+        description = ''
+        for i in range(length):
+            description += chr(desc_ptr[i])
+
+        self._lib.drop_chars(desc_ptr, length)
+        return description.splitlines()
+
+    def set_oscillation_threshold(self, toggles:int):
+        self._lib.set_oscillation_threshold(self._sim, c_ulonglong(toggles))
+
     def get_width(self, location):
         path_ptr,path_len = str_to_ptr(location)
         return self._lib.get_width(self._graph, path_ptr, path_len)
@@ -126,10 +177,14 @@ class Node(object):
     def __setattr__(self, name, value):
         path = super(Node, self).__getattribute__('_path')
         sim  = super(Node, self).__getattribute__('_sim')
-        sim.set_value(path + '.' + name, value)
+        if path is None:
+            sim.set_value(name, value)
+        else:
+            sim.set_value(path + '.' + name, value)
 
 
 sys.ps1 = 'rva> '
-simulation = Simulation()
+# the top module can be passed as an argument, e.g. to simulate a single module, otherwise rva.toml decides
+simulation = Simulation(*sys.argv[1:2])
 top = Node(simulation)
 
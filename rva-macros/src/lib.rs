@@ -0,0 +1,249 @@
+//! Macros that embed rva designs in Rust code. The design is parsed and linked at compile time,
+//! so syntax and link errors are reported as compile errors. The macros evaluate to a simulation
+//! of the top module with typed accessors for its ports:
+//!
+//! ```
+//! use rva_macros::rva;
+//!
+//! let mut adder = rva! {
+//!     module HalfAdder(a, b) -> (sum, carry) {
+//!         sum = a ^ b;
+//!         carry = a & b;
+//!     }
+//! };
+//! adder.set_a(true);
+//! adder.set_b(true);
+//! adder.settle().unwrap();
+//! assert!(adder.get_carry());
+//! ```
+//!
+//! For every port `p` there is a `get_p`, and for every input a `set_p`. Ports of a single bit are
+//! `bool`, wider ones the smallest unsigned integer that fits, but at most `u64`. Other wires
+//! can be accessed by path through the `sim` field.
+
+use proc_macro::{Delimiter, Span, TokenStream, TokenTree};
+use quote::{format_ident, quote, quote_spanned};
+use rva::{Design, WireKind};
+use syn::parse::{Parse, ParseStream};
+use syn::{Ident, LitStr, Token};
+
+use std::path::PathBuf;
+
+/// The text of the tokens, with whitespace wherever there was some in the source.
+/// The span of every token is kept to report errors at the right place.
+#[derive(Default)]
+struct Source {
+    text: String,
+    /// offset of every token in the text
+    spans: Vec<(usize, Span)>,
+    /// line and column where the last token ended
+    end: Option<(usize, usize)>,
+}
+
+impl Source {
+    fn push(&mut self, text: &str, span: Span) {
+        let start = span.start();
+        match self.end {
+            Some((line, _)) if start.line() > line => self.text.push('\n'),
+            Some((_, column)) if start.column() > column => self.text.push(' '),
+            _ => {},
+        }
+        self.spans.push((self.text.len(), span));
+        self.text.push_str(text);
+        let end = span.end();
+        self.end = Some((end.line(), end.column()));
+    }
+
+    fn tokens(&mut self, stream: TokenStream) {
+        for tree in stream {
+            match tree {
+                TokenTree::Group(group) => {
+                    let (open, close) = match group.delimiter() {
+                        Delimiter::Parenthesis => ("(", ")"),
+                        Delimiter::Brace => ("{", "}"),
+                        Delimiter::Bracket => ("[", "]"),
+                        Delimiter::None => {
+                            self.tokens(group.stream());
+                            continue;
+                        },
+                    };
+                    self.push(open, group.span_open());
+                    self.tokens(group.stream());
+                    self.push(close, group.span_close());
+                },
+                TokenTree::Ident(ident) => self.push(&ident.to_string(), ident.span()),
+                TokenTree::Punct(punct) => self.push(&punct.as_char().to_string(), punct.span()),
+                TokenTree::Literal(literal) => self.push(&literal.to_string(), literal.span()),
+            }
+        }
+    }
+
+    /// span of the first token on the line of the text, counted from 1
+    fn line_span(&self, line: usize) -> Span {
+        self.spans
+            .iter()
+            .find(|(offset, _)| self.text[..*offset].matches('\n').count() + 1 >= line)
+            .map(|(_, span)| *span)
+            .unwrap_or_else(Span::call_site)
+    }
+}
+
+fn error(span: Span, message: String) -> TokenStream {
+    let span = proc_macro2::Span::from(span);
+    quote_spanned!(span=> compile_error!(#message)).into()
+}
+
+/// Without an explicit top module it is the one called `Top`, or the only one that is defined.
+fn find_top(design: &Design) -> Result<String, String> {
+    let names = design.module_names();
+    match names.as_slice() {
+        _ if names.contains(&"Top") => Ok(String::from("Top")),
+        [name] => Ok(name.to_string()),
+        _ => Err(String::from("the design has no module called 'Top', name the top module with `top = Name`")),
+    }
+}
+
+/// `source` is the expression the design is parsed from at runtime
+fn expand(design: &Design, top: &str, source: proc_macro2::TokenStream) -> Result<TokenStream, String> {
+    let sim = design.elaborate(top).map_err(|e| e.to_string())?;
+
+    let mut accessors = Vec::new();
+    for (name, kind, width) in sim.ports() {
+        let getter = format_ident!("get_{}", name);
+        let setter = format_ident!("set_{}", name);
+        let (value_type, get) = match width {
+            1 => (quote!(bool), quote!(self.sim.get(#name).expect("port of the top module") != 0)),
+            _ => {
+                let value_type = match width {
+                    0..=8 => quote!(u8),
+                    9..=16 => quote!(u16),
+                    17..=32 => quote!(u32),
+                    _ => quote!(u64),
+                };
+                (value_type.clone(), quote!(self.sim.get(#name).expect("port of the top module") as #value_type))
+            },
+        };
+        accessors.push(quote! {
+            pub fn #getter(&self) -> #value_type {
+                #get
+            }
+        });
+        if kind != WireKind::Output {
+            accessors.push(quote! {
+                pub fn #setter(&mut self, value: #value_type) {
+                    if let ::core::result::Result::Err(e) = self.sim.set(#name, value as u64) {
+                        panic!("{}", e);
+                    }
+                }
+            });
+        }
+    }
+
+    let ident = format_ident!("{}", top);
+    Ok(quote! {
+        {
+            #[allow(dead_code)]
+            struct #ident {
+                pub sim: ::rva::Simulator,
+            }
+
+            #[allow(dead_code)]
+            impl #ident {
+                #(#accessors)*
+
+                /// runs the simulation until it is stable and checks the assertions and properties
+                pub fn settle(&mut self) -> ::core::result::Result<(), ::rva::Error> {
+                    self.sim.settle()
+                }
+
+                /// settles, then raises and lowers the clock once per cycle
+                pub fn clock(&mut self, path: &str, cycles: usize) -> ::core::result::Result<(), ::rva::Error> {
+                    self.sim.clock(path, cycles)
+                }
+            }
+
+            #ident {
+                sim: ::rva::Design::from_source(#source)
+                    .and_then(|design| design.elaborate(#top))
+                    .expect("the design was checked at compile time"),
+            }
+        }
+    }.into())
+}
+
+/// Simulation of the rva modules written inside of the macro. The top module can be chosen
+/// with `top = Name;` in front of the modules.
+#[proc_macro]
+pub fn rva(input: TokenStream) -> TokenStream {
+    let mut tokens: Vec<TokenTree> = input.into_iter().collect();
+    let mut top = None;
+    if let [TokenTree::Ident(keyword), TokenTree::Punct(eq), TokenTree::Ident(name), TokenTree::Punct(semi), ..] = tokens.as_slice() {
+        if keyword.to_string() == "top" && eq.as_char() == '=' && semi.as_char() == ';' {
+            top = Some(name.to_string());
+            tokens.drain(..4);
+        }
+    }
+
+    let mut source = Source::default();
+    source.tokens(tokens.into_iter().collect());
+
+    let design = match Design::from_source(&source.text) {
+        Ok(design) => design,
+        Err(rva::Error::Parse { line, message, .. }) => {
+            return error(source.line_span(line), format!("rva: {}", message));
+        },
+        Err(e) => return error(Span::call_site(), format!("rva: {}", e)),
+    };
+    let top = match top.map(Ok).unwrap_or_else(|| find_top(&design)) {
+        Ok(top) => top,
+        Err(e) => return error(Span::call_site(), format!("rva: {}", e)),
+    };
+
+    let text = &source.text;
+    expand(&design, &top, quote!(#text)).unwrap_or_else(|e| error(Span::call_site(), format!("rva: {}", e)))
+}
+
+struct Include {
+    path: LitStr,
+    top: Option<Ident>,
+}
+
+impl Parse for Include {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let path = input.parse()?;
+        let mut top = None;
+        if input.parse::<Option<Token![,]>>()?.is_some() && !input.is_empty() {
+            let keyword: Ident = input.parse()?;
+            if keyword != "top" {
+                return Err(syn::Error::new(keyword.span(), "expected `top = Name`"));
+            }
+            input.parse::<Token![=]>()?;
+            top = Some(input.parse()?);
+            input.parse::<Option<Token![,]>>()?;
+        }
+        Ok(Include { path, top })
+    }
+}
+
+/// Simulation of the modules in the file, relative to the directory of the crate's Cargo.toml.
+/// The top module can be chosen with `include_rva!("cpu.rva", top = Cpu)`.
+#[proc_macro]
+pub fn include_rva(input: TokenStream) -> TokenStream {
+    let include = syn::parse_macro_input!(input as Include);
+    let span = include.path.span().unwrap();
+
+    let dir = std::env::var_os("CARGO_MANIFEST_DIR").map(PathBuf::from).unwrap_or_default();
+    let path = dir.join(include.path.value());
+    let design = match Design::from_files(&[&path]) {
+        Ok(design) => design,
+        Err(e) => return error(span, format!("rva: {}", e)),
+    };
+    let top = match include.top.map(|t| Ok(t.to_string())).unwrap_or_else(|| find_top(&design)) {
+        Ok(top) => top,
+        Err(e) => return error(span, format!("rva: {}", e)),
+    };
+
+    // include_str! makes the crate rebuild when the file changes
+    let path = path.to_string_lossy().into_owned();
+    expand(&design, &top, quote!(include_str!(#path))).unwrap_or_else(|e| error(span, format!("rva: {}", e)))
+}
@@ -0,0 +1,45 @@
+use rva_macros::{include_rva, rva};
+
+#[test]
+fn inline_design_test() {
+    let mut acc = rva! {
+        top = Acc;
+
+        // adds the input on every rising edge of the clock
+        module Acc(clk, reset, in[4]) -> (out[6], overflow) {
+            wire sum[6], carry;
+            Adder4 low(a=out[0:3], b=in) -> (sum=sum[0:3], cout=carry);
+            sum[4] = out[4] ^ carry;
+            sum[5] = out[5] ^ (out[4] & carry);
+            overflow = out[5] & out[4] & carry;
+            Reg4 reg_low(clk, reset, d=sum[0:3]) -> (q=out[0:3]);
+            Reg2 reg_high(clk, reset, d=sum[4:5]) -> (q=out[4:5]);
+        }
+    };
+    acc.set_reset(true);
+    acc.clock("clk", 1).unwrap();
+    acc.set_reset(false);
+    acc.set_in(0xF);
+    acc.clock("clk", 3).unwrap();
+    let out: u8 = acc.get_out();
+    assert_eq!(out, 45);
+    assert!(!acc.get_overflow());
+    assert_eq!(acc.sim.get("reg_low.q").unwrap(), 45 & 0xF);
+}
+
+#[test]
+#[should_panic(expected = "does not fit")]
+fn value_too_wide_test() {
+    let mut not = rva! {
+        module Inv(a[2]) -> (b[2]) { b = !a; }
+    };
+    not.set_a(4);
+}
+
+#[test]
+fn included_design_test() {
+    let mut xor = include_rva!("../example/gates.rva", top = Xor4);
+    xor.set_in(0b1011);
+    xor.settle().unwrap();
+    assert!(xor.get_out());
+}
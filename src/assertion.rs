@@ -0,0 +1,124 @@
+use crate::parsed::*;
+use crate::netgraph::*;
+use crate::net::*;
+
+/// an assertion that did not hold in a stable state of the simulation
+#[derive(Debug)]
+pub struct AssertionFailure {
+    /// path of the instance the assertion belongs to, empty for the root
+    pub path: String,
+    pub module_name: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for AssertionFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let path = if self.path.is_empty() { "<root>" } else { &self.path };
+        write!(f, "Assertion failed in '{}' ({}): {}", path, self.module_name, self.message)
+    }
+}
+
+/// checks that all wires used in an operation exist in the module
+pub fn validate(op: &Operation, module: &Module) -> Result<(), String> {
+    match op {
+        Operation::Wire(bus) => {
+            for part in bus.iter() {
+                if let WirePart::Local { name, range } = part {
+                    let wire = match module.locals.iter().find(|w| &w.name == name) {
+                        Some(w) => w,
+                        None => return Err(format!("No local wire with name '{}'.", name)),
+                    };
+                    if let WireRange::Ranged { from, to } = range {
+                        if from > to || *to >= wire.width {
+                            return Err(format!(
+                                "[{}:{}] is not a valid subset of Wire '{}[{}]'.",
+                                from, to, wire.name, wire.width
+                            ));
+                        }
+                    }
+                }
+            }
+            Ok(())
+        },
+        Operation::And(op1, op2) | Operation::Or(op1, op2) | 
+        Operation::Xor(op1, op2) | Operation::Eq(op1, op2) => {
+            validate(op1, module)?;
+            validate(op2, module)
+        },
+        Operation::Not(op) | Operation::AndReduce(op) | 
+        Operation::OrReduce(op) | Operation::XorReduce(op) => validate(op, module),
+    }
+}
+
+fn bus_values(bus: &WireBus, graph: &GraphModule, sim: &Simulation) -> Vec<bool> {
+    let mut values = Vec::new();
+    for part in bus.iter() {
+        match part {
+            WirePart::Constant(c) => values.extend(c.iter().copied()),
+            WirePart::Local { name, range } => {
+                let wire = graph.locals.iter().find(|w| &w.name == name).expect("validated wire");
+                let addrs = match range {
+                    WireRange::Ranged { from, to } => &wire.values[*from..=*to],
+                    WireRange::Total => &wire.values[..],
+                };
+                values.extend(addrs.iter().map(|&a| sim.get_value(a)));
+            },
+        }
+    }
+    values
+}
+
+fn bitwise(a: Vec<bool>, b: Vec<bool>, f: fn(bool, bool) -> bool) -> Vec<bool> {
+    let width = a.len().max(b.len());
+    (0..width)
+        .map(|i| f(a.get(i).copied().unwrap_or(false), b.get(i).copied().unwrap_or(false)))
+        .collect()
+}
+
+/// evaluates an operation on the current values of the simulation
+pub fn evaluate(op: &Operation, graph: &GraphModule, sim: &Simulation) -> Vec<bool> {
+    let eval = |op: &Operation| evaluate(op, graph, sim);
+    match op {
+        Operation::Wire(bus) => bus_values(bus, graph, sim),
+        Operation::And(op1, op2) => bitwise(eval(op1), eval(op2), |a, b| a && b),
+        Operation::Or(op1, op2) => bitwise(eval(op1), eval(op2), |a, b| a || b),
+        Operation::Xor(op1, op2) => bitwise(eval(op1), eval(op2), |a, b| a ^ b),
+        Operation::Eq(op1, op2) => vec![!bitwise(eval(op1), eval(op2), |a, b| a ^ b).contains(&true)],
+        Operation::Not(op) => eval(op).into_iter().map(|b| !b).collect(),
+        Operation::AndReduce(op) => vec![eval(op).into_iter().all(|b| b)],
+        Operation::OrReduce(op) => vec![eval(op).into_iter().any(|b| b)],
+        Operation::XorReduce(op) => vec![eval(op).into_iter().fold(false, |a, b| a ^ b)],
+    }
+}
+
+/// evaluates the assertions of the module and all of its instances
+pub fn check_assertions(graph: &GraphModule, sim: &Simulation) -> Vec<AssertionFailure> {
+    let mut failures = Vec::new();
+    check_instance(graph, String::new(), sim, &mut failures);
+    failures
+}
+
+fn check_instance(graph: &GraphModule, path: String, sim: &Simulation, failures: &mut Vec<AssertionFailure>) {
+    for assertion in graph.assertions.iter() {
+        let enabled = match &assertion.enable {
+            Some(enable) => evaluate(enable, graph, sim).contains(&true),
+            None => true,
+        };
+        if enabled && evaluate(&assertion.condition, graph, sim).contains(&false) {
+            failures.push(AssertionFailure {
+                path: path.clone(),
+                module_name: graph.module_name.clone(),
+                message: assertion.message.clone().unwrap_or_else(|| assertion.source.clone()),
+            });
+        }
+    }
+
+    for inst in graph.instances.iter() {
+        let inst_path = if path.is_empty() {
+            inst.name.clone()
+        } else {
+            format!("{}.{}", path, inst.name)
+        };
+        check_instance(inst, inst_path, sim, failures);
+    }
+}
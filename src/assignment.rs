@@ -1,8 +1,35 @@
 use crate::parsed::*;
 
+use std::collections::*;
+
+/// a single bit of an operation after constant folding
+enum Folded {
+    /// an existing bit, either a constant or a bit of a wire
+    Bit(WireBus),
+    /// a gate that still needs to be instantiated
+    Gate(&'static str, Vec<WireBus>),
+}
+
+impl Folded {
+    fn constant(bit: bool) -> Self {
+        Folded::Bit(vec![WirePart::constant(vec![bit])])
+    }
+}
+
+/// returns the value of a single bit bus if it is constant
+fn constant_bit(bus: &WireBus) -> Option<bool> {
+    match bus.as_slice() {
+        [WirePart::Constant(c)] if c.len() == 1 => Some(c[0]),
+        _ => None,
+    }
+}
+
 pub struct Resolver<'a> {
     module: &'a mut Module,
     counter: u64,
+    /// outputs of the gates that have already been instantiated, 
+    /// used to share identical subexpressions within the module
+    gates: HashMap<(&'static str, Vec<WireBus>), WireBus>,
 }
 
 impl<'a> Resolver<'a> {
@@ -10,6 +37,7 @@ impl<'a> Resolver<'a> {
         Self {
             module,
             counter: 0,
+            gates: HashMap::new(),
         }
     }
 
@@ -29,171 +57,295 @@ impl<'a> Resolver<'a> {
     fn resolve_assignment(&mut self, assignment: WireAssignment) -> Result<(), ()> {
         let bus = assignment.bus;
         let operation = assignment.operation;
+        if operation.width(self.module)? != self.bus_width(&bus)? {
+            return Err(());
+        }
         self.resolve_operation(operation, Some(bus))?;
         Ok(())
     }
 
-    fn unary_operation(&mut self, input: &WireBus, output: &WireBus, gate_type: &str) -> Result<(), ()> {
-        let width = self.bus_width(input)?;
-        for i in 0..width {
-            let ini = self.index_bus(input, i)?;
-            let outi = self.index_bus(output, i)?;
-            let name = self.generate_name();
-            let gate = Instance {
-                module: gate_type.to_string(),
-                name: name.to_string(),
-                inputs: vec![Connection {
-                    local: ini,
-                    module: String::from("in"),
-                }],
-                outputs: vec![Connection {
-                    local: outi,
-                    module: String::from("out"),
-                }],
-            };
-            self.module.instances.push(gate);
+    /// instantiates a flip-flop for every bit of the register, 
+    /// which takes the value of the operation on the rising edge of the clock
+    pub fn resolve_register(&mut self, name: &str, clock: &str, operation: Operation) -> Result<(), ()> {
+        let q = vec![WirePart::total(name)];
+        let width = self.bus_width(&q)?;
+        if operation.width(self.module)? != width {
+            return Err(());
         }
-        Ok(())
-    }
 
-    fn binary_operation(&mut self, in1: &WireBus, in2: &WireBus, out: &WireBus, gate_type: &str) -> Result<(), ()> {
-        let width = self.bus_width(in1)?;
+        let d = self.resolve_operation(operation, None)?;
         for i in 0..width {
-            let in1i = self.index_bus(in1, i)?;
-            let in2i = self.index_bus(in2, i)?;
-            let outi = self.index_bus(out, i)?;
+            let di = self.index_bus(&d, i)?;
+            let qi = self.index_bus(&q, i)?;
             let name = self.generate_name();
-            let gate = Instance {
-                module: gate_type.to_string(),
-                name: name.to_string(),
+            self.module.instances.push(Instance {
+                module: String::from("DFlipFlop"),
+                name,
                 inputs: vec![
                     Connection {
-                        local: in1i,
-                        module: String::from("a"),
+                        local: vec![WirePart::total(clock)],
+                        module: String::from("clk"),
                     },
                     Connection {
-                        local: in2i,
-                        module: String::from("b"),
+                        local: di,
+                        module: String::from("d"),
                     },
                 ],
                 outputs: vec![Connection {
-                    local: outi,
-                    module: String::from("out"),
+                    local: qi,
+                    module: String::from("q"),
                 }],
-            };
-            self.module.instances.push(gate);
+            });
         }
         Ok(())
     }
 
-    fn reduce_operation(&mut self, input: WireBus, out: &WireBus, gate_type: &str) -> Result<(), ()> {
-        let width = self.bus_width(&input)?;
-        assert!(width > 0);
+    /// lowers all signals of a property to single bits, which are high if all bits of the operation are high
+    pub fn resolve_property(&mut self, property: Property<Operation>) -> Result<Property<WireBus>, ()> {
+        property.try_map(&mut |op| {
+            let bus = self.resolve_operation(op, None)?;
+            let all = self.reduce_operation(bus, "And")?;
+            self.resolve_bits(vec![all], None)
+        })
+    }
 
-        if width == 1 {
-            // simply connect input to output
-            self.unary_operation(&input, out, "Buffer")?;
-        } else {
-            let (part1, part2) = self.slice_bus(input)?;
-
-            let parts = vec![part1, part2];
-            let mut parts_result = vec![];
-
-            for part in parts.into_iter() {
-                // only insert a gate if there are more than 2 inputs
-                if self.bus_width(&part)? == 1 {
-                    parts_result.push(part);
-                } else {
-                    let wire = self.create_bus(1);
-                    self.reduce_operation(part, &wire, gate_type)?;
-                    parts_result.push(wire);
+    /// instantiates a single bit gate, inputs are named `in` for unary gates and `a`, `b` for binary gates
+    fn gate(&mut self, gate_type: &str, inputs: Vec<WireBus>, output: WireBus) {
+        let ports: &[&str] = if inputs.len() == 1 { &["in"] } else { &["a", "b"] };
+        let name = self.generate_name();
+        let gate = Instance {
+            module: gate_type.to_string(),
+            name,
+            inputs: inputs
+                .into_iter()
+                .zip(ports.iter())
+                .map(|(local, port)| Connection {
+                    local,
+                    module: port.to_string(),
+                })
+                .collect(),
+            outputs: vec![Connection {
+                local: output,
+                module: String::from("out"),
+            }],
+        };
+        self.module.instances.push(gate);
+    }
+
+    /// turns folded bits into a bus, only bits that still need a gate get wires allocated
+    fn resolve_bits(&mut self, bits: Vec<Folded>, output: Option<WireBus>) -> Result<WireBus, ()> {
+        if let Some(output) = output {
+            for (i, bit) in bits.into_iter().enumerate() {
+                let outi = self.index_bus(&output, i)?;
+                match bit {
+                    Folded::Bit(bus) => match constant_bit(&bus) {
+                        // a constant can be driven by a single inverter instead of a buffer
+                        Some(c) => self.gate("Not", vec![vec![WirePart::constant(vec![!c])]], outi),
+                        None => self.gate("Buffer", vec![bus], outi),
+                    },
+                    Folded::Gate(gate_type, inputs) => {
+                        self.gates.entry((gate_type, inputs.clone())).or_insert_with(|| outi.clone());
+                        self.gate(gate_type, inputs, outi);
+                    },
                 }
             }
+            return Ok(output);
+        }
 
-            self.binary_operation(&parts_result[0], &parts_result[1], out, gate_type)?;
+        let bits: Vec<_> = bits.into_iter().map(|bit| self.lookup_gate(bit)).collect();
+        let gates = bits.iter().filter(|b| matches!(b, Folded::Gate(..))).count();
+        let wire = if gates > 0 { Some(self.create_wire(gates)) } else { None };
+
+        let mut result = Vec::new();
+        let mut gate_idx = 0;
+        for bit in bits.into_iter() {
+            match bit {
+                Folded::Bit(mut bus) => result.append(&mut bus),
+                Folded::Gate(gate_type, inputs) => {
+                    let name = wire.as_ref().unwrap();
+                    let outi = vec![WirePart::ranged(name, gate_idx, gate_idx)];
+                    gate_idx += 1;
+                    self.gates.insert((gate_type, inputs.clone()), outi.clone());
+                    self.gate(gate_type, inputs, outi.clone());
+                    result.extend(outi);
+                },
+            }
         }
+        Ok(result)
+    }
 
-        Ok(())
+    /// replaces a gate by the output of an identical gate if there is one
+    fn lookup_gate(&self, bit: Folded) -> Folded {
+        if let Folded::Gate(gate_type, inputs) = &bit {
+            let mut key = (*gate_type, inputs.clone());
+            if let Some(out) = self.gates.get(&key) {
+                return Folded::Bit(out.clone());
+            }
+            // all binary gates are commutative
+            key.1.reverse();
+            if let Some(out) = self.gates.get(&key) {
+                return Folded::Bit(out.clone());
+            }
+        }
+        bit
+    }
+
+    fn fold_not(&mut self, bit: Folded) -> Result<Folded, ()> {
+        Ok(match bit {
+            Folded::Bit(bus) => match constant_bit(&bus) {
+                Some(c) => Folded::constant(!c),
+                None => Folded::Gate("Not", vec![bus]),
+            },
+            Folded::Gate("Not", mut inputs) => Folded::Bit(inputs.remove(0)),
+            gate => Folded::Gate("Not", vec![self.resolve_bits(vec![gate], None)?]),
+        })
+    }
+
+    fn fold_binary(&mut self, in1: WireBus, in2: WireBus, gate_type: &'static str) -> Result<Folded, ()> {
+        let folded = match (constant_bit(&in1), constant_bit(&in2)) {
+            (Some(a), Some(b)) => Folded::constant(match gate_type {
+                "And" => a && b,
+                "Or" => a || b,
+                _ => a ^ b,
+            }),
+            (Some(c), None) => return self.fold_with_constant(c, in2, gate_type),
+            (None, Some(c)) => return self.fold_with_constant(c, in1, gate_type),
+            (None, None) => Folded::Gate(gate_type, vec![in1, in2]),
+        };
+        Ok(folded)
+    }
+
+    fn fold_with_constant(&mut self, c: bool, bus: WireBus, gate_type: &'static str) -> Result<Folded, ()> {
+        Ok(match (gate_type, c) {
+            ("And", false) => Folded::constant(false),
+            ("Or", true) => Folded::constant(true),
+            ("Xor", true) => self.fold_not(Folded::Bit(bus))?,
+            _ => Folded::Bit(bus),
+        })
     }
 
-    /// slices a bus in half
-    fn slice_bus(&self, bus: WireBus) -> Result<(WireBus, WireBus), ()> {
-        let width = self.bus_width(&bus)?;
-        assert!(width >= 2);
-        let mid = width / 2;
-        let mut part1 = Vec::new();
-        let mut part2 = Vec::new();
+    fn binary_operation(&mut self, in1: &WireBus, in2: &WireBus, gate_type: &'static str) -> Result<Vec<Folded>, ()> {
+        let width = self.bus_width(in1)?;
+        let mut bits = Vec::with_capacity(width);
         for i in 0..width {
-            let mut partial = self.index_bus(&bus, i)?;
-            if i < mid {
-                part1.append(&mut partial);
-            } else {
-                part2.append(&mut partial);
-            }
+            let in1i = self.index_bus(in1, i)?;
+            let in2i = self.index_bus(in2, i)?;
+            bits.push(self.fold_binary(in1i, in2i, gate_type)?);
         }
-        Ok((part1, part2))
+        Ok(bits)
     }
 
-    fn resolve_operation(&mut self, op: Operation, output: Option<WireBus>) -> Result<WireBus, ()> {
-        let width = op.width(self.module)?;
+    fn reduce_operation(&mut self, input: WireBus, gate_type: &'static str) -> Result<Folded, ()> {
+        let width = self.bus_width(&input)?;
+        assert!(width > 0);
 
-        if let Operation::Wire(bus) = op {
-            match output {
-                Some(o) => { 
-                    self.unary_operation(&bus, &o, "Buffer")?; 
-                    return Ok(o);
-                },
-                None => return Ok(bus),
+        let mut parity = false;
+        let mut bits = Vec::new();
+        for i in 0..width {
+            let bit = self.index_bus(&input, i)?;
+            match (gate_type, constant_bit(&bit)) {
+                ("And", Some(false)) => return Ok(Folded::constant(false)),
+                ("Or", Some(true)) => return Ok(Folded::constant(true)),
+                (_, Some(c)) => parity ^= c,
+                (_, None) => bits.push(bit),
             }
         }
 
-        let output = match output {
-            Some(o) => o,
-            None => self.create_bus(width),
-        };
+        if bits.is_empty() {
+            // only constants left, which are the identity of 'And' and 'Or'
+            return Ok(Folded::constant(match gate_type {
+                "And" => true,
+                "Or" => false,
+                _ => parity,
+            }));
+        }
 
-        match op {
-            Operation::Wire(_) => unreachable!(),
+        let reduced = self.reduce_tree(bits, gate_type)?;
+        if gate_type == "Xor" && parity {
+            self.fold_not(reduced)
+        } else {
+            Ok(reduced)
+        }
+    }
 
-            // TODO make this less repetitive?
-            Operation::Not(op) => {
-                let input = self.resolve_operation(*op, None)?;
-                self.unary_operation(&input, &output, "Not")?;
-            },
-            Operation::And(op1, op2) => {
-                let in1 = self.resolve_operation(*op1, None)?;
-                let in2 = self.resolve_operation(*op2, None)?;
-                self.binary_operation(&in1, &in2, &output, "And")?;
-            },
-            Operation::Or(op1, op2) => {
-                let in1 = self.resolve_operation(*op1, None)?;
-                let in2 = self.resolve_operation(*op2, None)?;
-                self.binary_operation(&in1, &in2, &output, "Or")?;
-            },
-            Operation::Xor(op1, op2) => {
-                let in1 = self.resolve_operation(*op1, None)?;
-                let in2 = self.resolve_operation(*op2, None)?;
-                self.binary_operation(&in1, &in2, &output, "Xor")?;
-            },
-            Operation::AndReduce(op) => {
-                let input = self.resolve_operation(*op, None)?;
-                self.reduce_operation(input, &output, "And")?;
+    /// reduces single bit buses by building a tree of gates
+    fn reduce_tree(&mut self, mut bits: Vec<WireBus>, gate_type: &'static str) -> Result<Folded, ()> {
+        assert!(!bits.is_empty());
+
+        if bits.len() == 1 {
+            return Ok(Folded::Bit(bits.remove(0)));
+        }
+
+        let part2 = bits.split_off(bits.len() / 2);
+        let mut parts = Vec::new();
+        for part in vec![bits, part2].into_iter() {
+            let reduced = self.reduce_tree(part, gate_type)?;
+            parts.push(self.resolve_bits(vec![reduced], None)?);
+        }
+        Ok(Folded::Gate(gate_type, parts))
+    }
+
+    fn resolve_operation(&mut self, op: Operation, output: Option<WireBus>) -> Result<WireBus, ()> {
+        let bits = match op {
+            Operation::Wire(bus) => {
+                match output {
+                    Some(_) => {
+                        let width = self.bus_width(&bus)?;
+                        let mut bits = Vec::with_capacity(width);
+                        for i in 0..width {
+                            bits.push(Folded::Bit(self.index_bus(&bus, i)?));
+                        }
+                        bits
+                    },
+                    None => return Ok(bus),
+                }
             },
-            Operation::OrReduce(op) => {
+            Operation::Not(op) => {
                 let input = self.resolve_operation(*op, None)?;
-                self.reduce_operation(input, &output, "Or")?;
+                let width = self.bus_width(&input)?;
+                let mut bits = Vec::with_capacity(width);
+                for i in 0..width {
+                    let bit = Folded::Bit(self.index_bus(&input, i)?);
+                    bits.push(self.fold_not(bit)?);
+                }
+                bits
             },
-            Operation::XorReduce(op) => {
-                let input = self.resolve_operation(*op, None)?;
-                self.reduce_operation(input, &output, "Xor")?;
+            Operation::And(op1, op2) => self.resolve_binary(*op1, *op2, "And")?,
+            Operation::Or(op1, op2) => self.resolve_binary(*op1, *op2, "Or")?,
+            Operation::Xor(op1, op2) => self.resolve_binary(*op1, *op2, "Xor")?,
+            Operation::Eq(op1, op2) => {
+                // the narrower operand is zero-extended, like assertions compare values
+                let mut in1 = self.resolve_operation(*op1, None)?;
+                let mut in2 = self.resolve_operation(*op2, None)?;
+                let (width1, width2) = (self.bus_width(&in1)?, self.bus_width(&in2)?);
+                if width1 < width2 {
+                    in1.push(WirePart::constant(vec![false; width2 - width1]));
+                } else if width2 < width1 {
+                    in2.push(WirePart::constant(vec![false; width1 - width2]));
+                }
+
+                // equal if no bit differs
+                let differences = self.binary_operation(&in1, &in2, "Xor")?;
+                let differences = self.resolve_bits(differences, None)?;
+                let any_difference = self.reduce_operation(differences, "Or")?;
+                vec![self.fold_not(any_difference)?]
             },
-        }
-        Ok(output)
+            Operation::AndReduce(op) => self.resolve_reduce(*op, "And")?,
+            Operation::OrReduce(op) => self.resolve_reduce(*op, "Or")?,
+            Operation::XorReduce(op) => self.resolve_reduce(*op, "Xor")?,
+        };
+        self.resolve_bits(bits, output)
+    }
+
+    fn resolve_binary(&mut self, op1: Operation, op2: Operation, gate_type: &'static str) -> Result<Vec<Folded>, ()> {
+        let in1 = self.resolve_operation(op1, None)?;
+        let in2 = self.resolve_operation(op2, None)?;
+        self.binary_operation(&in1, &in2, gate_type)
     }
 
-    fn create_bus(&mut self, width: usize) -> WireBus {
-        let wire = self.create_wire(width);
-        vec![WirePart::ranged(wire, 0, width-1)]
+    fn resolve_reduce(&mut self, op: Operation, gate_type: &'static str) -> Result<Vec<Folded>, ()> {
+        let input = self.resolve_operation(op, None)?;
+        Ok(vec![self.reduce_operation(input, gate_type)?])
     }
 
     /// creates a wire in the module with a generated name and returns the name
@@ -203,6 +355,8 @@ impl<'a> Resolver<'a> {
             name: name.clone(),
             kind: WireKind::Private,
             width,
+            default: None,
+            init: None,
         };
         self.module.locals.push(wire);
         name
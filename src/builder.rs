@@ -0,0 +1,242 @@
+use crate::parsed::*;
+use crate::assignment::Resolver;
+use crate::assertion;
+use crate::design::Error;
+
+use std::ops::{BitAnd, BitOr, BitXor, Not};
+
+/// the lowest `width` bits of the value, least significant bit first
+pub fn bits(value: u64, width: usize) -> Vec<bool> {
+    (0..width).map(|idx| idx < 64 && (value >> idx) & 1 == 1).collect()
+}
+
+/// a whole local wire
+pub fn wire(name: &str) -> Operation {
+    Operation::Wire(vec![WirePart::total(name)])
+}
+
+/// the bits `from` to `to` (inclusive) of a local wire
+pub fn slice(name: &str, from: usize, to: usize) -> Operation {
+    Operation::Wire(vec![WirePart::ranged(name, from, to)])
+}
+
+pub fn constant(value: u64, width: usize) -> Operation {
+    Operation::Wire(vec![WirePart::constant(bits(value, width))])
+}
+
+impl Operation {
+    /// a single bit that is high if both operands are equal
+    pub fn equals(self, other: Operation) -> Operation {
+        Operation::Eq(Box::new(self), Box::new(other))
+    }
+
+    pub fn and_reduce(self) -> Operation {
+        Operation::AndReduce(Box::new(self))
+    }
+
+    pub fn or_reduce(self) -> Operation {
+        Operation::OrReduce(Box::new(self))
+    }
+
+    pub fn xor_reduce(self) -> Operation {
+        Operation::XorReduce(Box::new(self))
+    }
+}
+
+impl BitAnd for Operation {
+    type Output = Operation;
+    fn bitand(self, other: Operation) -> Operation {
+        Operation::And(Box::new(self), Box::new(other))
+    }
+}
+
+impl BitOr for Operation {
+    type Output = Operation;
+    fn bitor(self, other: Operation) -> Operation {
+        Operation::Or(Box::new(self), Box::new(other))
+    }
+}
+
+impl BitXor for Operation {
+    type Output = Operation;
+    fn bitxor(self, other: Operation) -> Operation {
+        Operation::Xor(Box::new(self), Box::new(other))
+    }
+}
+
+impl Not for Operation {
+    type Output = Operation;
+    fn not(self) -> Operation {
+        Operation::Not(Box::new(self))
+    }
+}
+
+impl Instance {
+    pub fn new(module: &str, name: &str) -> Self {
+        Instance { module: module.to_string(), name: name.to_string(), inputs: Vec::new(), outputs: Vec::new() }
+    }
+
+    /// connects the input of the instance to local wires
+    pub fn input(mut self, port: &str, local: WireBus) -> Self {
+        self.inputs.push(Connection { local, module: port.to_string() });
+        self
+    }
+
+    /// connects the output of the instance to local wires, an empty bus discards it
+    pub fn output(mut self, port: &str, local: WireBus) -> Self {
+        self.outputs.push(Connection { local, module: port.to_string() });
+        self
+    }
+}
+
+/// Constructs a module in code. Assignments and registers are lowered to gates on `build`,
+/// the same way as those of parsed modules.
+///
+/// ```
+/// use rva::builder::*;
+///
+/// let mut adder = ModuleBuilder::new("HalfAdder");
+/// adder.input("a", 1).input("b", 1).output("sum", 1).output("carry", 1);
+/// adder.assign("sum", wire("a") ^ wire("b"));
+/// adder.assign("carry", wire("a") & wire("b"));
+///
+/// let mut design = rva::Design::default();
+/// design.add_module(adder.build()?);
+/// let mut sim = design.elaborate("HalfAdder")?;
+/// sim.set("a", 1)?;
+/// sim.set("b", 1)?;
+/// sim.settle()?;
+/// assert_eq!(sim.get("carry")?, 1);
+/// # Ok::<(), rva::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct ModuleBuilder {
+    module: Module,
+    assignments: Vec<WireAssignment>,
+    /// name of the register wire, its clock and its next value
+    registers: Vec<(String, String, Operation)>,
+}
+
+impl ModuleBuilder {
+    pub fn new(name: &str) -> Self {
+        ModuleBuilder {
+            module: Module {
+                name: name.to_string(),
+                locals: Vec::new(),
+                instances: Vec::new(),
+                primitive: false,
+                latch: false,
+                assertions: Vec::new(),
+                properties: Vec::new(),
+            },
+            assignments: Vec::new(),
+            registers: Vec::new(),
+        }
+    }
+
+    fn local(&mut self, name: &str, width: usize, kind: WireKind) -> &mut Self {
+        self.module.locals.push(Wire { name: name.to_string(), width, kind, default: None, init: None });
+        self
+    }
+
+    pub fn input(&mut self, name: &str, width: usize) -> &mut Self {
+        self.local(name, width, WireKind::Input)
+    }
+
+    /// an input that is tied to `default` if it is not assigned in an instantiation
+    pub fn input_with_default(&mut self, name: &str, width: usize, default: u64) -> &mut Self {
+        self.module.locals.push(Wire {
+            name: name.to_string(),
+            width,
+            kind: WireKind::Input,
+            default: Some(bits(default, width)),
+            init: None,
+        });
+        self
+    }
+
+    pub fn inout(&mut self, name: &str, width: usize) -> &mut Self {
+        self.local(name, width, WireKind::InOut)
+    }
+
+    pub fn output(&mut self, name: &str, width: usize) -> &mut Self {
+        self.local(name, width, WireKind::Output)
+    }
+
+    pub fn wire(&mut self, name: &str, width: usize) -> &mut Self {
+        self.local(name, width, WireKind::Private)
+    }
+
+    /// a local wire that may be driven by multiple tri-state gates
+    pub fn bus(&mut self, name: &str, width: usize) -> &mut Self {
+        self.local(name, width, WireKind::Bus)
+    }
+
+    /// marks the module with `@latch`, so its feedback loops are intended
+    pub fn latch(&mut self) -> &mut Self {
+        self.module.latch = true;
+        self
+    }
+
+    pub fn instance(&mut self, instance: Instance) -> &mut Self {
+        self.module.instances.push(instance);
+        self
+    }
+
+    /// drives the whole wire with the operation
+    pub fn assign(&mut self, name: &str, operation: Operation) -> &mut Self {
+        self.assign_bus(vec![WirePart::total(name)], operation)
+    }
+
+    pub fn assign_bus(&mut self, bus: WireBus, operation: Operation) -> &mut Self {
+        self.assignments.push(WireAssignment { bus, operation });
+        self
+    }
+
+    /// a register that takes the value of the operation on the rising edge of the clock
+    pub fn register(&mut self, name: &str, width: usize, clock: &str, operation: Operation) -> &mut Self {
+        self.wire(name, width);
+        self.registers.push((name.to_string(), clock.to_string(), operation));
+        self
+    }
+
+    /// an assertion that holds if all bits of the condition are high
+    pub fn assert(&mut self, condition: Operation, message: Option<&str>) -> &mut Self {
+        let source = match message {
+            Some(message) => format!("assert {} \"{}\";", condition, message),
+            None => format!("assert {};", condition),
+        };
+        self.module.assertions.push(Assertion { condition, enable: None, message: message.map(String::from), source });
+        self
+    }
+
+    /// checks the assignments and lowers them to gates
+    pub fn build(&self) -> Result<Module, Error> {
+        let mut module = self.module.clone();
+        let name = module.name.clone();
+        let invalid = |message: String| Error::InvalidModule { module: name.clone(), message };
+
+        let registers = self.registers.iter().map(|(name, _, op)| (vec![WirePart::total(name)], op));
+        let assignments = self.assignments.iter().map(|a| (a.bus.clone(), &a.operation));
+        for (bus, operation) in assignments.chain(registers) {
+            let target = Operation::Wire(bus);
+            assertion::validate(&target, &module).map_err(invalid)?;
+            assertion::validate(operation, &module).map_err(invalid)?;
+            if target.width(&module) != operation.width(&module) {
+                return Err(invalid(format!("'{}' and '{}' differ in width", target, operation)));
+            }
+        }
+        for assertion in module.assertions.iter() {
+            assertion::validate(&assertion.condition, &module).map_err(invalid)?;
+        }
+
+        let mut resolver = Resolver::new(&mut module);
+        resolver.resolve_assignments(self.assignments.clone())
+            .map_err(|_| invalid(String::from("the assignments can not be lowered to gates")))?;
+        for (name, clock, operation) in self.registers.iter() {
+            resolver.resolve_register(name, clock, operation.clone())
+                .map_err(|_| invalid(format!("the register '{}' can not be lowered to gates", name)))?;
+        }
+        Ok(module)
+    }
+}
@@ -0,0 +1,493 @@
+use crate::parsed::*;
+use crate::parsing::{integer, wire_path, parse_complete};
+use crate::netgraph::*;
+use crate::net::*;
+use crate::export::{self, Format};
+use crate::testbench;
+use crate::vectors;
+use crate::repl;
+use crate::project::Project;
+use crate::lint::{self, Level};
+
+use std::path::{Path, PathBuf};
+
+/// the design was checked, simulated or tested successfully
+pub const SUCCESS: i32 = 0;
+/// the design could not be built, or a test or check failed
+pub const FAILURE: i32 = 1;
+/// the arguments were invalid or the sources could not be read
+pub const USAGE_ERROR: i32 = 2;
+
+const USAGE: &str = "\
+Usage: rva <command> [options] [paths...]
+
+Commands:
+    check     parse and link the design, and check it for combinational loops
+    stats     print the size of the linked design
+    sim       simulate the design with the given stimulus
+    test      run all testbench blocks
+    export    write the netlist of the design
+    vectors   apply a vector file and compare the expected values
+    repl      simulate the design interactively
+    fmt       format the source files in place
+    lsp       run the language server on stdin and stdout
+    lint      warn about unused and suspicious constructs in the design
+    help      print this message
+
+Options:
+    --manifest <file>       project manifest, rva.toml in the current directory by default
+    --profile <name>        (sim) profile of the manifest whose settings are used,
+                            options given on the command line take precedence
+    --top <module>          module that is elaborated, from the manifest or 'Top' by default
+    --set <path>=<value>    (sim) sets a wire before running, can be repeated
+    --clock <path>          (sim) clock that is toggled after the wires are set
+    --cycles <n>            (sim) number of clock cycles, 0 by default
+    --print <path>          (sim) wire or instance that is printed at the end, can be repeated
+    --format <format>       (export) text, dot or blif, text by default
+    --output <file>         (export) file that is written instead of stdout
+    --vectors <file>        (vectors) .csv file or whitespace separated .vec file, the header
+                            names the wires, inputs of the top module are set and all other
+                            wires are compared, 'x' or '-' are don't-cares
+    --report <file>         (vectors) file the mismatches are written to instead of stdout
+    --allow <lint>          (lint) leaves the lint out, can be repeated
+    --warn <lint>           (lint) reports the lint as a warning, which is the default
+    --deny <lint>           (lint) reports the lint as an error and fails,
+                            levels on the command line take precedence over the manifest
+    --check                 (fmt) list the files that are not formatted instead of writing them,
+                            fails if there are any
+
+Sources are all .rva files in the given paths, or in the sources of the manifest if none are given.
+Without a manifest the current directory is used.
+Exits with 0 on success, 1 if the design or a test failed and 2 on invalid arguments or I/O errors.";
+
+const COMMANDS: [&str; 10] = ["check", "stats", "sim", "test", "export", "vectors", "repl", "fmt", "lsp", "lint"];
+
+struct Options {
+    command: String,
+    manifest: Option<PathBuf>,
+    profile: Option<String>,
+    top: Option<String>,
+    paths: Vec<PathBuf>,
+    sets: Vec<(Vec<String>, u64)>,
+    clock: Option<Vec<String>>,
+    cycles: Option<usize>,
+    prints: Vec<Vec<String>>,
+    format: Format,
+    output: Option<PathBuf>,
+    vectors: Option<PathBuf>,
+    report: Option<PathBuf>,
+    check: bool,
+    lints: Vec<(String, Level)>,
+}
+
+fn parse_path(i: &str) -> Result<Vec<String>, String> {
+    parse_complete(i, wire_path).ok_or_else(|| format!("invalid path '{}'", i))
+}
+
+fn parse_value(i: &str) -> Result<u64, String> {
+    parse_complete(i, integer).ok_or_else(|| format!("invalid value '{}'", i))
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut args = args.iter();
+    let command = args.next().ok_or("no command given")?.clone();
+    if !COMMANDS.contains(&command.as_str()) {
+        return Err(format!("unknown command '{}'", command));
+    }
+
+    let mut options = Options {
+        command,
+        manifest: None,
+        profile: None,
+        top: None,
+        paths: Vec::new(),
+        sets: Vec::new(),
+        clock: None,
+        cycles: None,
+        prints: Vec::new(),
+        format: Format::Text,
+        output: None,
+        vectors: None,
+        report: None,
+        check: false,
+        lints: Vec::new(),
+    };
+
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            options.paths.push(PathBuf::from(arg));
+            continue;
+        }
+        if arg == "--check" {
+            options.check = true;
+            continue;
+        }
+        let value = args.next().ok_or_else(|| format!("missing value for '{}'", arg))?;
+        match arg.as_str() {
+            "--manifest" => options.manifest = Some(PathBuf::from(value)),
+            "--profile" => options.profile = Some(value.clone()),
+            "--top" => options.top = Some(value.clone()),
+            "--set" => {
+                let (path, number) = value
+                    .split_once('=')
+                    .ok_or_else(|| format!("expected <path>=<value>, got '{}'", value))?;
+                options.sets.push((parse_path(path)?, parse_value(number)?));
+            },
+            "--clock" => options.clock = Some(parse_path(value)?),
+            "--cycles" => options.cycles = Some(value.parse().map_err(|_| format!("invalid cycle count '{}'", value))?),
+            "--print" => options.prints.push(parse_path(value)?),
+            "--format" => options.format = Format::from_name(value).ok_or_else(|| format!("unknown format '{}'", value))?,
+            "--output" => options.output = Some(PathBuf::from(value)),
+            "--vectors" => options.vectors = Some(PathBuf::from(value)),
+            "--report" => options.report = Some(PathBuf::from(value)),
+            "--allow" => options.lints.push((value.clone(), Level::Allow)),
+            "--warn" => options.lints.push((value.clone(), Level::Warn)),
+            "--deny" => options.lints.push((value.clone(), Level::Deny)),
+            _ => return Err(format!("unknown option '{}'", arg)),
+        }
+    }
+
+    if options.command == "vectors" && options.vectors.is_none() {
+        return Err(String::from("vectors needs a --vectors file"));
+    }
+    Ok(options)
+}
+
+fn load_project(options: &Options) -> Result<Project, String> {
+    let mut project = match &options.manifest {
+        Some(path) => Project::from_file(path)?,
+        None => Project::load(Path::new("."))?,
+    };
+
+    // paths on the command line are relative to the working directory, not to the manifest,
+    // the root becomes absolute as well, so that the excluded paths still match the sources
+    if !options.paths.is_empty() {
+        let cwd = std::env::current_dir().map_err(|e| e.to_string())?;
+        project.root = cwd.join(&project.root);
+        project.manifest.sources = options.paths.iter().map(|p| cwd.join(p)).collect();
+    }
+    Ok(project)
+}
+
+/// merges the profile into the options and returns the top module
+fn apply_profile(options: &mut Options, project: &Project) -> Result<String, String> {
+    let mut top = project.top().to_string();
+    if let Some(name) = &options.profile {
+        let profile = project.profile(name)?;
+        if let Some(profile_top) = &profile.top {
+            top = profile_top.clone();
+        }
+
+        // wires set on the command line are applied later and overwrite the ones of the profile
+        let mut sets = Vec::new();
+        for (path, value) in profile.set.iter() {
+            sets.push((parse_path(path)?, *value));
+        }
+        sets.append(&mut options.sets);
+        options.sets = sets;
+
+        if options.clock.is_none() {
+            options.clock = profile.clock.as_deref().map(parse_path).transpose()?;
+        }
+        options.cycles = options.cycles.or(profile.cycles);
+        if options.prints.is_empty() {
+            options.prints = profile.print.iter().map(|p| parse_path(p)).collect::<Result<_, _>>()?;
+        }
+    }
+
+    if options.cycles.unwrap_or(0) > 0 && options.clock.is_none() {
+        return Err(String::from("--cycles needs a --clock"));
+    }
+    Ok(options.top.clone().unwrap_or(top))
+}
+
+/// runs the command line interface with the arguments (without the program name) and returns the exit code
+pub fn run(args: &[String]) -> i32 {
+    if matches!(args.first().map(String::as_str), None | Some("help") | Some("--help") | Some("-h")) {
+        println!("{}", USAGE);
+        return if args.is_empty() { USAGE_ERROR } else { SUCCESS };
+    }
+
+    let mut options = match parse_args(args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            return USAGE_ERROR;
+        },
+    };
+
+    // the language server reads the project of the workspace the editor opens
+    if options.command == "lsp" {
+        return match crate::lsp::run() {
+            Ok(()) => SUCCESS,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                FAILURE
+            },
+        };
+    }
+
+    let project = match load_project(&options) {
+        Ok(project) => project,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return USAGE_ERROR;
+        },
+    };
+    let top = match apply_profile(&mut options, &project) {
+        Ok(top) => top,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return USAGE_ERROR;
+        },
+    };
+
+    if options.command == "fmt" {
+        return format(&project, options.check);
+    }
+
+    let sources = match crate::load_sources(&project) {
+        Ok(sources) => sources,
+        Err(e @ crate::Error::Io { .. }) => {
+            eprintln!("error: {}", e);
+            return USAGE_ERROR;
+        },
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return FAILURE;
+        },
+    };
+
+    if options.command == "test" {
+        return test(&sources.libraries, &sources.modules, &sources.tests);
+    }
+
+    let files = sources.files.len();
+    let (graph, mut sim) = match crate::build_with_libraries(sources.libraries.clone(), sources.modules.clone(), &top) {
+        Ok(built) => built,
+        Err(e) => {
+            eprintln!("error ({:?}): {}", e.kind, e.description);
+            return FAILURE;
+        },
+    };
+
+    match options.command.as_str() {
+        "check" => check(&top, files, &graph, &sim),
+        "stats" => stats(&graph, &sim),
+        "sim" => simulate(&options, &top, &graph, &mut sim),
+        "export" => export(&options, &graph, &sim),
+        "lint" => lint(&options, &project, &sources, &graph, &sim),
+        "vectors" => apply_vectors(&options, &graph, &mut sim),
+        "repl" => {
+            let mut repl = repl::Repl::new(graph, sim);
+            repl.set_sources(project, top);
+            match repl::run(repl) {
+                Ok(()) => SUCCESS,
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    USAGE_ERROR
+                },
+            }
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn format(project: &Project, check: bool) -> i32 {
+    let files = match crate::read_files(&project.source_paths(), &project.excluded_paths()) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return USAGE_ERROR;
+        },
+    };
+
+    let mut code = SUCCESS;
+    for (path, source) in files.iter() {
+        let formatted = match crate::format::format(source) {
+            Ok(formatted) => formatted,
+            Err(crate::format::Error::Line(line, message)) => {
+                eprintln!("error: {}", crate::Error::Parse { path: path.clone(), line, message });
+                code = FAILURE;
+                continue;
+            },
+            Err(crate::format::Error::Changed) => {
+                eprintln!("error: {}: formatting would change the design, the file is left as it is", path.display());
+                code = FAILURE;
+                continue;
+            },
+        };
+        if formatted == *source {
+            continue;
+        }
+        if check {
+            println!("{}: not formatted", path.display());
+            code = FAILURE;
+        } else if let Err(e) = std::fs::write(path, formatted) {
+            eprintln!("error: {}", crate::Error::Io { path: path.clone(), error: e });
+            return USAGE_ERROR;
+        } else {
+            println!("formatted {}", path.display());
+        }
+    }
+    code
+}
+
+fn lint(options: &Options, project: &Project, sources: &crate::Sources, graph: &GraphModule, sim: &Simulation) -> i32 {
+    let mut config = match lint::Config::from_settings(&project.manifest.lint) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return USAGE_ERROR;
+        },
+    };
+    for (name, level) in options.lints.iter() {
+        if let Err(e) = config.set(name, *level) {
+            eprintln!("error: {}", e);
+            return USAGE_ERROR;
+        }
+    }
+
+    let warnings = lint::lint(&config, &sources.libraries, &sources.modules, &sources.tests, graph, sim);
+    for warning in warnings.iter() {
+        println!("{}", warning);
+    }
+    let errors = warnings.iter().filter(|w| w.level == Level::Deny).count();
+    println!("\n{} warnings, {} errors", warnings.len() - errors, errors);
+
+    if errors == 0 { SUCCESS } else { FAILURE }
+}
+
+fn test(libraries: &[Module], mods: &[Module], tests: &[TestBench]) -> i32 {
+    let results = crate::run_tests(libraries, mods, tests);
+    for result in results.iter() {
+        println!("{}", result);
+    }
+    let passed = results.iter().filter(|r| r.passed()).count();
+    println!("\n{} passed, {} failed", passed, results.len() - passed);
+
+    if passed == results.len() { SUCCESS } else { FAILURE }
+}
+
+fn count_instances(graph: &GraphModule, counts: &mut std::collections::BTreeMap<String, usize>) {
+    for inst in graph.instances.iter() {
+        *counts.entry(inst.module_name.clone()).or_insert(0) += 1;
+        count_instances(inst, counts);
+    }
+}
+
+fn check(top: &str, files: usize, graph: &GraphModule, sim: &Simulation) -> i32 {
+    let loops = crate::loops::find(sim.net(), graph);
+    if loops.is_empty() {
+        println!("'{}' linked successfully from {} files", top, files);
+        return SUCCESS;
+    }
+    for wires in loops.iter() {
+        eprintln!("error: combinational loop through {}", wires.join(", "));
+    }
+    eprintln!("'{}' has {} combinational loops, modules with intended feedback can be marked with '@latch'", top, loops.len());
+    FAILURE
+}
+
+fn stats(graph: &GraphModule, sim: &Simulation) -> i32 {
+    sim.print_stats();
+
+    let mut counts = std::collections::BTreeMap::new();
+    count_instances(graph, &mut counts);
+    println!("Instances:");
+    for (module, count) in counts.iter() {
+        println!("    {}: {}", module, count);
+    }
+    SUCCESS
+}
+
+fn simulate(options: &Options, top: &str, graph: &GraphModule, sim: &mut Simulation) -> i32 {
+    let mut steps: Vec<_> = options.sets
+        .iter()
+        .map(|(path, value)| TestStep::Set(path.clone(), *value))
+        .collect();
+    steps.push(TestStep::Settle);
+    if let Some(clock) = &options.clock {
+        steps.push(TestStep::Clock(clock.clone(), options.cycles.unwrap_or(0)));
+    }
+    let stimulus = TestBench { name: String::from("sim"), module: top.to_string(), steps };
+    let result = testbench::run(&stimulus, graph, sim);
+
+    let prints = if options.prints.is_empty() { vec![Vec::new()] } else { options.prints.clone() };
+    for path in prints.iter() {
+        match graph.display_path(String::new(), path, sim) {
+            Ok(description) => println!("{}", description),
+            Err(Error::InvalidPath(e)) => eprintln!("error: {}", e),
+        }
+    }
+
+    for failure in result.failures.iter() {
+        eprintln!("{}", failure);
+    }
+    if result.passed() { SUCCESS } else { FAILURE }
+}
+
+fn export(options: &Options, graph: &GraphModule, sim: &Simulation) -> i32 {
+    let netlist = match export::export(graph, sim, options.format) {
+        Ok(netlist) => netlist,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return FAILURE;
+        },
+    };
+    write_output(&options.output, &netlist)
+}
+
+/// writes to the file, or to stdout if there is none
+fn write_output(path: &Option<PathBuf>, content: &str) -> i32 {
+    match path {
+        Some(path) => match std::fs::write(path, content) {
+            Ok(()) => SUCCESS,
+            Err(e) => {
+                eprintln!("error: {}: {}", path.display(), e);
+                USAGE_ERROR
+            },
+        },
+        None => {
+            print!("{}", content);
+            SUCCESS
+        },
+    }
+}
+
+fn apply_vectors(options: &Options, graph: &GraphModule, sim: &mut Simulation) -> i32 {
+    let path = options.vectors.as_ref().unwrap();
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("error: {}: {}", path.display(), e);
+            return USAGE_ERROR;
+        },
+    };
+
+    let separator = if path.extension().map(|e| e == "csv").unwrap_or(false) { Some(',') } else { None };
+    let result = vectors::parse(&source, separator).and_then(|file| {
+        vectors::run(&file, graph, sim).map(|mismatches| (file.rows.len(), mismatches))
+    });
+    let (rows, mismatches) = match result {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("error: {}: {}", path.display(), e);
+            return FAILURE;
+        },
+    };
+
+    let mut report = String::new();
+    for mismatch in mismatches.iter() {
+        report += &format!("{}\n", mismatch);
+    }
+    let mut failed: Vec<_> = mismatches.iter().map(|m| m.line).collect();
+    failed.dedup();
+    report += &format!("{} of {} rows failed\n", failed.len(), rows);
+
+    match write_output(&options.report, &report) {
+        SUCCESS if mismatches.is_empty() => SUCCESS,
+        SUCCESS => FAILURE,
+        error => error,
+    }
+}
@@ -0,0 +1,221 @@
+use crate::parsed::{Module, TestBench};
+use crate::parsing::{wire_path, parse_complete};
+use crate::netgraph::GraphModule;
+use crate::net::Simulation;
+use crate::parsed::WireKind;
+use crate::link::ErrorKind;
+use crate::project::Project;
+use crate::testbench::{self, TestResult, Oscillation};
+
+use std::path::{Path, PathBuf};
+
+/// everything that can go wrong when loading, elaborating or simulating a design
+#[derive(Debug)]
+pub enum Error {
+    /// a source file or directory could not be read
+    Io { path: PathBuf, error: std::io::Error },
+    /// the source file contains something that is not a valid declaration at the line
+    Parse { path: PathBuf, line: usize, message: String },
+    /// the project manifest could not be read
+    Manifest(String),
+    /// a module constructed in code is inconsistent
+    InvalidModule { module: String, message: String },
+    /// the design could not be elaborated
+    Link { kind: ErrorKind, message: String },
+    /// the text is not a path of the form `instance.wire`
+    InvalidPath(String),
+    UnknownWire(String),
+    /// the value has more bits than the wire
+    ValueTooWide { path: String, value: u64, width: usize },
+    /// the simulation did not become stable within the gate update limit
+    Unstable(String),
+    /// wires kept toggling while the simulation settled
+    Oscillating(Vec<Oscillation>),
+    /// assertions or properties failed while the simulation settled
+    Failed(Vec<String>),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            Error::Parse { path, line, message } => write!(f, "{}:{}: {}", path.display(), line, message),
+            Error::Manifest(message) => write!(f, "{}", message),
+            Error::InvalidModule { module, message } => write!(f, "invalid module '{}': {}", module, message),
+            Error::Link { kind, message } => write!(f, "{:?}: {}", kind, message),
+            Error::InvalidPath(path) => write!(f, "invalid path '{}'", path),
+            Error::UnknownWire(path) => write!(f, "unknown wire '{}'", path),
+            Error::ValueTooWide { path, value, width } => {
+                write!(f, "value {:#x} does not fit into '{}' ({} bits)", value, path, width)
+            },
+            Error::Unstable(message) => write!(f, "{}", message),
+            Error::Oscillating(wires) => {
+                write!(f, "simulation oscillates:")?;
+                for wire in wires.iter() {
+                    write!(f, "\n    {}", wire)?;
+                }
+                Ok(())
+            },
+            Error::Failed(failures) => write!(f, "{}", failures.join("\n")),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// The parsed sources of a design, which can be elaborated with any of its modules as top.
+///
+/// ```no_run
+/// let design = rva::Design::from_files(&["src"])?;
+/// let mut sim = design.elaborate("Adder4")?;
+/// sim.set("a", 3)?;
+/// sim.set("b", 4)?;
+/// sim.settle()?;
+/// assert_eq!(sim.get("sum")?, 7);
+/// # Ok::<(), rva::Error>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Design {
+    libraries: Vec<Module>,
+    modules: Vec<Module>,
+    tests: Vec<TestBench>,
+}
+
+impl Design {
+    /// parses all .rva files in the given files and directories
+    pub fn from_files<P: AsRef<Path>>(paths: &[P]) -> Result<Design, Error> {
+        let (modules, tests) = crate::parse_files(&crate::read_files(paths, &[])?)?;
+        Ok(Design { libraries: Vec::new(), modules, tests })
+    }
+
+    pub fn from_source(source: &str) -> Result<Design, Error> {
+        let (modules, tests) = crate::parse_files(&[(PathBuf::from("<source>"), source.to_string())])?;
+        Ok(Design { libraries: Vec::new(), modules, tests })
+    }
+
+    /// loads the sources and libraries of the project in the directory, see `rva.toml`
+    pub fn from_project(dir: &Path) -> Result<Design, Error> {
+        let project = Project::load(dir).map_err(Error::Manifest)?;
+        let sources = crate::load_sources(&project)?;
+        Ok(Design { libraries: sources.libraries, modules: sources.modules, tests: sources.tests })
+    }
+
+    /// adds a module, e.g. one constructed with a `ModuleBuilder`, which is linked like the parsed ones
+    pub fn add_module(&mut self, module: Module) {
+        self.modules.push(module);
+    }
+
+    /// the modules of the sources as they are linked, with all assignments lowered to gates
+    pub fn modules(&self) -> &[Module] {
+        &self.modules
+    }
+
+    /// names of the modules defined in the sources, without the ones of libraries and the standard library
+    pub fn module_names(&self) -> Vec<&str> {
+        self.modules.iter().map(|m| m.name.as_str()).collect()
+    }
+
+    /// links the design with `top` as root, the inputs of `top` start out with their default values
+    pub fn elaborate(&self, top: &str) -> Result<Simulator, Error> {
+        let (graph, sim) = crate::build_with_libraries(self.libraries.clone(), self.modules.clone(), top)
+            .map_err(|e| Error::Link { kind: e.kind, message: e.description })?;
+        Ok(Simulator { graph, sim })
+    }
+
+    /// runs all testbench blocks of the sources, each against a fresh simulation
+    pub fn run_tests(&self) -> Vec<TestResult> {
+        crate::run_tests(&self.libraries, &self.modules, &self.tests)
+    }
+}
+
+/// Simulation of an elaborated design. Wires are addressed by paths like `reg.dff_0.q`,
+/// values are read and written with the lowest bit of the wire as the lowest bit of the value.
+pub struct Simulator {
+    graph: GraphModule,
+    sim: Simulation,
+}
+
+impl Simulator {
+    fn address(&self, path: &str) -> Result<(Vec<String>, Vec<usize>), Error> {
+        let parts = parse_complete(path, wire_path).ok_or_else(|| Error::InvalidPath(path.to_string()))?;
+        let addr = testbench::address(&self.graph, &parts).map_err(|_| Error::UnknownWire(path.to_string()))?;
+        Ok((parts, addr))
+    }
+
+    /// name, kind and width of the inputs and outputs of the top module, in the order they are declared
+    pub fn ports(&self) -> Vec<(String, WireKind, usize)> {
+        self.graph.locals
+            .iter()
+            .filter(|w| w.kind.is_port())
+            .map(|w| (w.name.clone(), w.kind, w.values.len()))
+            .collect()
+    }
+
+    /// combinational loops outside of `@latch` modules, each with the names of its bits
+    pub fn combinational_loops(&self) -> Vec<Vec<String>> {
+        crate::loops::find(self.sim.net(), &self.graph)
+    }
+
+    /// number of toggles of a single wire after which `settle` fails as oscillating, 1000 by default,
+    /// large designs may need more
+    pub fn set_oscillation_threshold(&mut self, toggles: usize) {
+        self.sim.set_oscillation_threshold(toggles);
+    }
+
+    /// number of bits of the wire
+    pub fn width(&self, path: &str) -> Result<usize, Error> {
+        Ok(self.address(path)?.1.len())
+    }
+
+    /// sets the wire, the change only propagates on the next `settle`
+    pub fn set(&mut self, path: &str, value: u64) -> Result<(), Error> {
+        let (parts, addr) = self.address(path)?;
+        if addr.len() < 64 && value >> addr.len() != 0 {
+            return Err(Error::ValueTooWide { path: path.to_string(), value, width: addr.len() });
+        }
+        testbench::set(&self.graph, &mut self.sim, &parts, value).map_err(|_| Error::UnknownWire(path.to_string()))
+    }
+
+    /// value of the wire, only the lowest 64 bits are read
+    pub fn get(&self, path: &str) -> Result<u64, Error> {
+        let (parts, _) = self.address(path)?;
+        testbench::get(&self.graph, &self.sim, &parts).map_err(|_| Error::UnknownWire(path.to_string()))
+    }
+
+    fn settle_into(&mut self, failures: &mut Vec<String>) -> Result<(), Error> {
+        testbench::settle(&self.graph, &mut self.sim, failures).map_err(|message| {
+            if self.sim.is_oscillating() {
+                Error::Oscillating(testbench::oscillations(&self.graph, &self.sim))
+            } else {
+                Error::Unstable(message)
+            }
+        })
+    }
+
+    /// runs the simulation until it is stable and checks the assertions and properties of the design
+    pub fn settle(&mut self) -> Result<(), Error> {
+        let mut failures = Vec::new();
+        self.settle_into(&mut failures)?;
+        if failures.is_empty() { Ok(()) } else { Err(Error::Failed(failures)) }
+    }
+
+    /// settles, then raises and lowers the clock once per cycle and settles after every edge
+    pub fn clock(&mut self, path: &str, cycles: usize) -> Result<(), Error> {
+        let mut failures = Vec::new();
+        self.settle_into(&mut failures)?;
+        for _ in 0..cycles {
+            self.set(path, 1)?;
+            self.settle_into(&mut failures)?;
+            self.set(path, 0)?;
+            self.settle_into(&mut failures)?;
+        }
+        if failures.is_empty() { Ok(()) } else { Err(Error::Failed(failures)) }
+    }
+}
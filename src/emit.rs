@@ -0,0 +1,174 @@
+use crate::parsed::*;
+
+use std::fmt::{Display, Formatter, Result};
+
+/// bits are written with the most significant bit first
+fn constant(bits: &[bool]) -> String {
+    let digits: String = bits.iter().rev().map(|&b| if b { '1' } else { '0' }).collect();
+    format!("0b{}", digits)
+}
+
+fn wire(f: &mut Formatter, wire: &Wire) -> Result {
+    if wire.width == 1 {
+        write!(f, "{}", wire.name)
+    } else {
+        write!(f, "{}[{}]", wire.name, wire.width)
+    }
+}
+
+struct Bus<'a>(&'a [WirePart]);
+
+impl Display for Bus<'_> {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self.0 {
+            [] => write!(f, "_"),
+            [part] => write!(f, "{}", part),
+            parts => {
+                let parts: Vec<String> = parts.iter().map(|p| p.to_string()).collect();
+                write!(f, "{{{}}}", parts.join(", "))
+            },
+        }
+    }
+}
+
+impl Display for WirePart {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            WirePart::Local { name, range: WireRange::Total } => write!(f, "{}", name),
+            WirePart::Local { name, range: WireRange::Ranged { from, to } } if from == to => write!(f, "{}[{}]", name, from),
+            WirePart::Local { name, range: WireRange::Ranged { from, to } } => write!(f, "{}[{}:{}]", name, from, to),
+            WirePart::Constant(bits) => write!(f, "{}", constant(bits)),
+        }
+    }
+}
+
+/// binary operations are right associative without precedence, so nested ones are put in parentheses
+struct Operand<'a>(&'a Operation);
+
+impl Display for Operand<'_> {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self.0 {
+            Operation::And(..) | Operation::Or(..) | Operation::Xor(..) | Operation::Eq(..) => write!(f, "({})", self.0),
+            op => write!(f, "{}", op),
+        }
+    }
+}
+
+impl Display for Operation {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            Operation::Wire(bus) => write!(f, "{}", Bus(bus)),
+            Operation::And(a, b) => write!(f, "{} & {}", Operand(a), Operand(b)),
+            Operation::Or(a, b) => write!(f, "{} | {}", Operand(a), Operand(b)),
+            Operation::Xor(a, b) => write!(f, "{} ^ {}", Operand(a), Operand(b)),
+            Operation::Eq(a, b) => write!(f, "{} == {}", Operand(a), Operand(b)),
+            Operation::AndReduce(op) => write!(f, "&{}", Operand(op)),
+            Operation::OrReduce(op) => write!(f, "|{}", Operand(op)),
+            Operation::XorReduce(op) => write!(f, "^{}", Operand(op)),
+            Operation::Not(op) => write!(f, "!{}", Operand(op)),
+        }
+    }
+}
+
+impl Display for Sequence<WireBus> {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            Sequence::Holds(s) => write!(f, "{}", Bus(s)),
+            Sequence::Next(seq) => write!(f, "next {}", seq),
+            Sequence::Within(n, s) => write!(f, "within {} {}", n, Bus(s)),
+            Sequence::Until(s1, s2) => write!(f, "{} until {}", Bus(s1), Bus(s2)),
+        }
+    }
+}
+
+fn connections(connections: &[Connection]) -> String {
+    let connections: Vec<String> = connections
+        .iter()
+        .map(|c| format!("{}={}", c.module, Bus(&c.local)))
+        .collect();
+    connections.join(", ")
+}
+
+/// the declaration of a module up to its body, e.g. `module Adder(a[4], b[4]) -> (sum[4])`,
+/// which starts with `@latch` for latches
+pub struct Header<'a>(pub &'a Module);
+
+impl Display for Header<'_> {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        let module = self.0;
+        let keyword = if module.primitive { "primitive" } else { "module" };
+        if module.latch {
+            write!(f, "@latch ")?;
+        }
+        write!(f, "{} {}(", keyword, module.name)?;
+        let inputs = module.locals.iter().filter(|w| matches!(w.kind, WireKind::Input | WireKind::InOut));
+        for (idx, input) in inputs.enumerate() {
+            if idx > 0 {
+                write!(f, ", ")?;
+            }
+            if input.kind == WireKind::InOut {
+                write!(f, "inout ")?;
+            }
+            wire(f, input)?;
+            if let Some(default) = &input.default {
+                write!(f, " = {}", constant(default))?;
+            }
+        }
+        write!(f, ") -> (")?;
+        for (idx, output) in module.locals.iter().filter(|w| w.kind == WireKind::Output).enumerate() {
+            if idx > 0 {
+                write!(f, ", ")?;
+            }
+            wire(f, output)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Writes the module as source, which parses to an equivalent module.
+/// Assignments have already been lowered to gates, so they are written as instances.
+impl Display for Module {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "{}", Header(self))?;
+        if self.primitive {
+            return writeln!(f, ";");
+        }
+        writeln!(f, " {{")?;
+
+        for local in self.locals.iter() {
+            match local.kind {
+                WireKind::Private => {
+                    write!(f, "    wire ")?;
+                    wire(f, local)?;
+                    if let Some(init) = &local.init {
+                        write!(f, " init {}", constant(init))?;
+                    }
+                    writeln!(f, ";")?;
+                },
+                WireKind::Bus => {
+                    write!(f, "    bus ")?;
+                    wire(f, local)?;
+                    writeln!(f, ";")?;
+                },
+                _ => {},
+            }
+        }
+        for inst in self.instances.iter() {
+            writeln!(
+                f, "    {} {}({}) -> ({});",
+                inst.module, inst.name, connections(&inst.inputs), connections(&inst.outputs)
+            )?;
+        }
+        for assertion in self.assertions.iter() {
+            writeln!(f, "    {}", assertion.source)?;
+        }
+        for property in self.properties.iter() {
+            write!(f, "    property {} @ {}: ", property.name, Bus(&property.clock))?;
+            if let Some(antecedent) = &property.antecedent {
+                write!(f, "{} |-> ", Bus(antecedent))?;
+            }
+            writeln!(f, "{};", property.consequent)?;
+        }
+        writeln!(f, "}}")
+    }
+}
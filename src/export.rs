@@ -0,0 +1,119 @@
+use crate::parsed::WireKind;
+use crate::netgraph::*;
+use crate::net::*;
+
+use std::collections::*;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// one gate per line
+    Text,
+    /// graphviz graph
+    Dot,
+    /// berkeley logic interchange format
+    Blif,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "text" => Some(Format::Text),
+            "dot" => Some(Format::Dot),
+            "blif" => Some(Format::Blif),
+            _ => None,
+        }
+    }
+}
+
+/// names for all wires of the net, wires without a name in the graph are constants
+fn names(graph: &GraphModule, net: &Net) -> Vec<String> {
+    let mut names = graph.wire_names();
+    (0..net.wire_count())
+        .map(|addr| names.remove(&addr).unwrap_or_else(|| format!("const{}", addr)))
+        .collect()
+}
+
+fn ports(graph: &GraphModule, kind: WireKind) -> Vec<usize> {
+    graph.locals
+        .iter()
+        .filter(|w| w.kind == kind)
+        .flat_map(|w| w.values.iter().copied())
+        .collect()
+}
+
+fn text(graph: &GraphModule, net: &Net) -> String {
+    let names = names(graph, net);
+    let mut out = format!("# {}: {} wires, {} gates\n", graph.module_name, net.wire_count(), net.gates().len());
+    for gate in net.gates().iter() {
+        match *gate {
+            Gate::Nor { in1, in2, out: o } =>
+                writeln!(out, "nor {} {} {}", names[in1], names[in2], names[o]).unwrap(),
+            Gate::TriState { input, enable, out: o } =>
+                writeln!(out, "tristate {} {} {}", names[input], names[enable], names[o]).unwrap(),
+        }
+    }
+    out
+}
+
+fn dot(graph: &GraphModule, net: &Net) -> String {
+    let names = names(graph, net);
+    let mut out = format!("digraph \"{}\" {{\n", graph.module_name);
+    for (addr, name) in names.iter().enumerate() {
+        writeln!(out, "    w{} [label=\"{}\", shape=plaintext];", addr, name).unwrap();
+    }
+    for (idx, gate) in net.gates().iter().enumerate() {
+        let (label, inputs, o) = match *gate {
+            Gate::Nor { in1, in2, out: o } => ("NOR", [in1, in2], o),
+            Gate::TriState { input, enable, out: o } => ("TRI", [input, enable], o),
+        };
+        writeln!(out, "    g{} [label=\"{}\", shape=box];", idx, label).unwrap();
+        for input in inputs.iter() {
+            writeln!(out, "    w{} -> g{};", input, idx).unwrap();
+        }
+        writeln!(out, "    g{} -> w{};", idx, o).unwrap();
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn blif(graph: &GraphModule, net: &Net, sim: &Simulation) -> Result<String, String> {
+    let names = names(graph, net);
+    let join = |addrs: &[usize]| addrs.iter().map(|&a| names[a].as_str()).collect::<Vec<_>>().join(" ");
+
+    let inputs = ports(graph, WireKind::Input);
+    let outputs = ports(graph, WireKind::Output);
+    let mut out = format!(".model {}\n.inputs {}\n.outputs {}\n", graph.module_name, join(&inputs), join(&outputs));
+
+    let mut driven = HashSet::new();
+    for gate in net.gates().iter() {
+        match *gate {
+            Gate::Nor { in1, in2, out: o } => {
+                writeln!(out, ".names {} {} {}\n00 1", names[in1], names[in2], names[o]).unwrap();
+                driven.insert(o);
+            },
+            Gate::TriState { .. } => return Err(String::from("tri-state gates can not be exported to BLIF")),
+        }
+    }
+
+    // constants are wires without a driver that are not inputs
+    let inputs: HashSet<_> = inputs.into_iter().collect();
+    for (addr, name) in names.iter().enumerate() {
+        if !driven.contains(&addr) && !inputs.contains(&addr) {
+            let value = if sim.get_value(addr) { "\n1" } else { "" };
+            writeln!(out, ".names {}{}", name, value).unwrap();
+        }
+    }
+    out.push_str(".end\n");
+    Ok(out)
+}
+
+/// writes the net of the simulation in the format
+pub fn export(graph: &GraphModule, sim: &Simulation, format: Format) -> Result<String, String> {
+    let net = sim.net();
+    match format {
+        Format::Text => Ok(text(graph, net)),
+        Format::Dot => Ok(dot(graph, net)),
+        Format::Blif => blif(graph, net, sim),
+    }
+}
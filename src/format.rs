@@ -0,0 +1,365 @@
+use nom::{
+    IResult,
+    bytes::complete::*,
+    character::complete::*,
+    combinator::*,
+    sequence::*,
+    branch::*,
+};
+
+use crate::parsing::{declarations, Declaration};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// line of the first invalid declaration and why it is invalid
+    Line(usize, String),
+    /// the formatted source does not parse to the same declarations, which is a bug of the formatter
+    Changed,
+}
+
+/// lines of port and binding lists that are longer are broken after every port
+const MAX_WIDTH: usize = 100;
+const INDENT: &str = "    ";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Word,
+    Str,
+    Punct,
+    Comment,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Token<'a> {
+    pub kind: Kind,
+    pub text: &'a str,
+    /// byte offset of the token in the source
+    pub offset: usize,
+    /// number of line breaks between the previous token and this one
+    newlines: usize,
+}
+
+impl Token<'_> {
+    pub fn is(&self, text: &str) -> bool {
+        self.kind == Kind::Punct && self.text == text
+    }
+
+    /// whether an operand ends with the token, so that a following `&`, `|` or `^` is binary
+    fn ends_operand(&self) -> bool {
+        matches!(self.kind, Kind::Word | Kind::Str) || self.is(")") || self.is("]") || self.is("}")
+    }
+}
+
+fn token(i: &str) -> IResult<&str, (Kind, &str)> {
+    alt((
+            map(recognize(preceded(tag("//"), take_while(|c| c != '\n'))), |t| (Kind::Comment, t)),
+            map(recognize(delimited(char('"'), take_until("\""), char('"'))), |t| (Kind::Str, t)),
+            map(take_while1(|c: char| c.is_ascii_alphanumeric() || c == '_'), |t| (Kind::Word, t)),
+            map(alt((tag("|->"), tag("->"), tag("=="))), |t| (Kind::Punct, t)),
+            map(take(1usize), |t| (Kind::Punct, t)),
+    ))(i)
+}
+
+/// splits the source into words, punctuation, strings and comments
+pub fn lex(source: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = source;
+    loop {
+        let trimmed = rest.trim_start();
+        let newlines = rest[..rest.len() - trimmed.len()].matches('\n').count();
+        rest = trimmed;
+        match token(rest) {
+            Ok((next, (kind, text))) => {
+                let offset = source.len() - rest.len();
+                tokens.push(Token { kind, text: text.trim_end(), offset, newlines });
+                rest = next;
+            },
+            Err(_) => return tokens,
+        }
+    }
+}
+
+/// a statement or a comment on a line of its own
+enum Item<'a> {
+    Comment(Token<'a>),
+    Statement(Vec<Token<'a>>),
+}
+
+impl Item<'_> {
+    fn first(&self) -> Token<'_> {
+        match self {
+            Item::Comment(token) => *token,
+            Item::Statement(tokens) => tokens[0],
+        }
+    }
+}
+
+/// Splits the tokens into statements, which end with a `;` or the braces of a declaration body.
+/// Comments within a statement are moved in front of it, comments after the end of a statement stay there.
+fn items<'a>(tokens: &[Token<'a>]) -> Vec<(Item<'a>, Option<Token<'a>>)> {
+    let mut items: Vec<(Item, Option<Token>)> = Vec::new();
+    let mut statement = Vec::new();
+    let mut comments = Vec::new();
+    let mut in_body = false;
+    let mut depth = 0;
+
+    for token in tokens.iter().copied() {
+        if token.kind == Kind::Comment {
+            match items.last_mut() {
+                Some((_, trailing @ None)) if statement.is_empty() && token.newlines == 0 => *trailing = Some(token),
+                _ if statement.is_empty() => items.push((Item::Comment(token), None)),
+                _ => comments.push(token),
+            }
+            continue;
+        }
+
+        statement.push(token);
+        let end = match token.text {
+            "(" | "[" if token.kind == Kind::Punct => { depth += 1; false },
+            ")" | "]" if token.kind == Kind::Punct => { depth -= 1; false },
+            "{" if token.kind == Kind::Punct && !in_body => { in_body = true; true },
+            "{" if token.kind == Kind::Punct => { depth += 1; false },
+            "}" if token.kind == Kind::Punct && depth == 0 => { in_body = false; true },
+            "}" if token.kind == Kind::Punct => { depth -= 1; false },
+            ";" => depth == 0,
+            _ => false,
+        };
+        if end {
+            // the blank lines before the statement are kept in front of the comments that were moved
+            if let Some(comment) = comments.first_mut() {
+                comment.newlines = comment.newlines.max(statement[0].newlines);
+                statement[0].newlines = 1;
+            }
+            items.extend(comments.drain(..).map(|c| (Item::Comment(c), None)));
+            items.push((Item::Statement(std::mem::take(&mut statement)), None));
+        }
+    }
+    items
+}
+
+/// Writes the tokens of a statement on one line, or with one port per line if `column` is the
+/// column of the line start and the line would be too long.
+fn render(tokens: &[Token], column: usize) -> String {
+    let line = render_with(tokens, None);
+    if column + line.len() <= MAX_WIDTH || !tokens.iter().any(|t| t.is("(")) {
+        return line;
+    }
+    render_with(tokens, Some(column))
+}
+
+fn render_with(tokens: &[Token], break_lists: Option<usize>) -> String {
+    // a leading attribute like `@latch` is written tight
+    let attribute = tokens[0].is("@");
+    let header = tokens
+        .get(if attribute { 2 } else { 0 })
+        .is_some_and(|t| matches!(t.text, "module" | "primitive"));
+    let mut out = String::new();
+    let mut parens = 0;
+    let mut brackets = 0;
+    // the first line starts at `column`, the following ones are already indented in `out`
+    let column = break_lists.unwrap_or(0);
+    let mut line_start = 0;
+    let mut list_column = 0;
+    let mut braces = 0;
+    let mut prev: Option<Token> = None;
+    let mut prev_unary = false;
+
+    for token in tokens.iter() {
+        let binary = prev.map(|p| p.ends_operand()).unwrap_or(false);
+        let tight_eq = parens > 0 && !header;
+        let space = match prev {
+            None => false,
+            Some(_) if prev_unary => false,
+            Some(p) if p.is("@") && attribute && out.len() == 1 => false,
+            Some(p) if p.is("(") || p.is("[") || p.is(".") || p.is("*") => false,
+            Some(p) if p.is("{") && token.text != "{" => false,
+            Some(p) if p.is("=") && tight_eq => false,
+            Some(_) if token.kind == Kind::Punct && matches!(token.text, "," | ";" | ")" | "]" | ":" | "." | "*" | "[") => false,
+            Some(_) if token.is("}") => false,
+            Some(p) if token.is("(") => p.kind != Kind::Word,
+            Some(_) if token.is("=") && tight_eq => false,
+            Some(p) if p.is(":") => brackets == 0,
+            Some(_) => true,
+        };
+        if space {
+            out.push(' ');
+        }
+        out.push_str(token.text);
+
+        prev_unary = token.kind == Kind::Punct && match token.text {
+            "!" => true,
+            "&" | "|" | "^" => !binary,
+            _ => false,
+        };
+        match token.text {
+            "(" if token.kind == Kind::Punct => {
+                parens += 1;
+                if parens == 1 {
+                    list_column = out.len() - line_start + if line_start == 0 { column } else { 0 };
+                }
+            },
+            ")" if token.kind == Kind::Punct => parens -= 1,
+            "[" if token.kind == Kind::Punct => brackets += 1,
+            "]" if token.kind == Kind::Punct => brackets -= 1,
+            "{" if token.kind == Kind::Punct => braces += 1,
+            "}" if token.kind == Kind::Punct => braces -= 1,
+            // concatenations like `{a, b}` are not broken
+            "," if parens == 1 && brackets == 0 && braces == 0 && break_lists.is_some() => {
+                out.push('\n');
+                line_start = out.len();
+                out.push_str(&" ".repeat(list_column));
+                prev = None;
+                continue;
+            },
+            _ => {},
+        }
+        prev = Some(*token);
+    }
+    out
+}
+
+/// index of the `->` of an instance statement
+fn arrow(tokens: &[Token]) -> Option<usize> {
+    if tokens[0].is("@") || matches!(tokens[0].text, "module" | "primitive" | "test") {
+        return None;
+    }
+    let mut depth = 0;
+    for (idx, token) in tokens.iter().enumerate() {
+        match token.text {
+            "(" | "[" | "{" if token.kind == Kind::Punct => depth += 1,
+            ")" | "]" | "}" if token.kind == Kind::Punct => depth -= 1,
+            "->" if depth == 0 => return Some(idx),
+            _ => {},
+        }
+    }
+    None
+}
+
+fn column(source: &str, offset: usize) -> usize {
+    offset - source[..offset].rfind('\n').map_or(0, |n| n + 1)
+}
+
+/// For every item the width its instance is padded to before the `->`. Consecutive instances
+/// keep their `->` aligned if they were aligned in the source and fit on one line.
+fn alignment(source: &str, items: &[(Item, Option<Token>)]) -> Vec<Option<usize>> {
+    let arrows: Vec<Option<(usize, usize)>> = items
+        .iter()
+        .map(|(item, _)| match item {
+            Item::Statement(tokens) => {
+                let idx = arrow(tokens)?;
+                let line = render_with(tokens, None);
+                let fits = INDENT.len() + line.len() <= MAX_WIDTH;
+                fits.then(|| (render_with(&tokens[..idx], None).len(), column(source, tokens[idx].offset)))
+            },
+            Item::Comment(_) => None,
+        })
+        .collect();
+
+    let mut widths = vec![None; items.len()];
+    let mut start = 0;
+    while start < items.len() {
+        let (_, source_column) = match arrows[start] {
+            Some(arrow) => arrow,
+            None => {
+                start += 1;
+                continue;
+            },
+        };
+        // a run ends at a blank line or an instance whose `->` is in another column
+        let mut end = start + 1;
+        while end < items.len()
+            && items[end].0.first().newlines == 1
+            && arrows[end].is_some_and(|(_, c)| c == source_column)
+        {
+            end += 1;
+        }
+        let width = (start..end).filter_map(|i| arrows[i]).map(|(w, _)| w).max().unwrap_or(0);
+        let fits = (start..end).all(|i| match &items[i].0 {
+            Item::Statement(tokens) => {
+                INDENT.len() + render_with(tokens, None).len() + width - arrows[i].map_or(0, |(w, _)| w) <= MAX_WIDTH
+            },
+            Item::Comment(_) => false,
+        });
+        if end - start > 1 && fits {
+            for width_of in widths[start..end].iter_mut() {
+                *width_of = Some(width);
+            }
+        }
+        start = end;
+    }
+    widths
+}
+
+/// drops what formatting may change, which is only the source text of assertions
+fn normalize(declarations: Vec<Declaration>) -> Vec<Declaration> {
+    declarations
+        .into_iter()
+        .map(|d| match d {
+            Declaration::Module(mut m) => {
+                for assertion in m.assertions.iter_mut() {
+                    assertion.source.clear();
+                }
+                Declaration::Module(m)
+            },
+            test => test,
+        })
+        .collect()
+}
+
+/// Formats the source into the canonical layout, comments and the aligned `->` of instances are kept. Fails with the line of the
+/// first invalid declaration if the source does not parse.
+pub fn format(source: &str) -> Result<String, Error> {
+    let parsed = crate::parse_declarations(source).map_err(|(line, message)| Error::Line(line, message))?;
+
+    let mut out = String::new();
+    let mut in_body = false;
+    // a declaration ended, so the next item is separated by a blank line
+    let mut separate = false;
+    let tokens = lex(source);
+    let items = items(&tokens);
+    let aligned = alignment(source, &items);
+    for ((item, trailing), aligned) in items.iter().zip(aligned) {
+        let first = item.first();
+        let closes = matches!(item, Item::Statement(tokens) if tokens.len() == 1 && tokens[0].is("}")) && in_body;
+        if !out.is_empty() && !closes && (separate || (first.newlines > 1 && !out.ends_with("{\n"))) {
+            out.push('\n');
+        }
+        separate = false;
+
+        let indent = if in_body && !closes { INDENT } else { "" };
+        out.push_str(indent);
+        match item {
+            Item::Comment(comment) => out.push_str(comment.text),
+            Item::Statement(tokens) => {
+                match (aligned, arrow(tokens)) {
+                    (Some(width), Some(idx)) => {
+                        let prefix = render_with(&tokens[..idx], None);
+                        out.push_str(&format!("{:<width$} {}", prefix, render_with(&tokens[idx..], None), width = width));
+                    },
+                    _ => out.push_str(&render(tokens, indent.len())),
+                }
+                match tokens.last().map(|t| t.text) {
+                    Some("{") => in_body = true,
+                    Some("}") if in_body => {
+                        in_body = false;
+                        separate = true;
+                    },
+                    Some(";") if !in_body => separate = true,
+                    _ => {},
+                }
+            },
+        }
+        if let Some(comment) = trailing {
+            out.push(' ');
+            out.push_str(comment.text);
+        }
+        out.push('\n');
+    }
+
+    // the layout must not change the meaning
+    let unchanged = match declarations(&out) {
+        Ok(("", formatted)) => normalize(formatted) == normalize(parsed),
+        _ => false,
+    };
+    if unchanged { Ok(out) } else { Err(Error::Changed) }
+}
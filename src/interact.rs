@@ -3,6 +3,7 @@ use nom::*;
 use crate::net::*;
 use crate::netgraph::*;
 use crate::parsing::*;
+use crate::assertion::*;
 
 unsafe fn path<'a>(ptr: *const u8, len: u64) -> IResult<&'a str, Vec<String>> {
     let slice = std::slice::from_raw_parts(ptr, len as _);
@@ -10,11 +11,24 @@ unsafe fn path<'a>(ptr: *const u8, len: u64) -> IResult<&'a str, Vec<String>> {
     list(field_name, ".")(i)
 }
 
+/// Runs the simulation until it is stable, oscillating, or `count` gates have been updated (0 = unbounded).
+/// Once it is stable, bus contentions, failed assertions and failed properties are reported,
+/// an oscillation is reported with the wires that keep toggling.
+///
+/// # Safety
+/// `sim` and `graph` must have been created by `create_graph_simulation`.
 #[no_mangle]
-pub unsafe extern "C" fn simulate(sim: &mut Simulation, mut count: u64) -> bool {
+pub unsafe extern "C" fn simulate(sim: &mut Simulation, graph: &GraphModule, mut count: u64) -> bool {
     let bounded = count > 0;
 
     while !sim.is_stable() {
+        if sim.is_oscillating() {
+            eprintln!("Simulation oscillates:");
+            for oscillation in crate::testbench::oscillations(graph, sim) {
+                eprintln!("    {}", oscillation);
+            }
+            return false;
+        }
         sim.update();
         if bounded {
             count -= 1;
@@ -24,9 +38,36 @@ pub unsafe extern "C" fn simulate(sim: &mut Simulation, mut count: u64) -> bool
         }
     }
 
+    if sim.is_stable() {
+        report_contention(sim, graph);
+        for failure in check_assertions(graph, sim) {
+            eprintln!("{}", failure);
+        }
+        for failure in sim.sample_monitors() {
+            eprintln!("{}", failure);
+        }
+    }
+
     sim.is_stable()
 }
 
+/// Prints all wires that are driven by more than one tri-state gate and returns whether there are any.
+#[no_mangle]
+pub extern "C" fn report_contention(sim: &Simulation, graph: &GraphModule) -> bool {
+    let contentions = sim.contentions();
+    for &wire in contentions.iter() {
+        match graph.wire_path(wire) {
+            Some(path) => eprintln!("Bus contention on '{}'", path),
+            None => eprintln!("Bus contention on wire {}", wire),
+        }
+    }
+    !contentions.is_empty()
+}
+
+/// Reads the value of the wire at `path` into a newly allocated buffer and returns its length.
+///
+/// # Safety
+/// `path_ptr` must point to `path_len` bytes of UTF-8, the buffer must be freed with `drop_bools`.
 #[no_mangle]
 pub unsafe extern "C" fn get_value(
     sim: &Simulation, 
@@ -59,6 +100,10 @@ pub unsafe extern "C" fn get_value(
     }
 }
 
+/// Sets the value of the wire at `path`.
+///
+/// # Safety
+/// `path_ptr` must point to `path_len` bytes of UTF-8 and `values` to `values_len` bools.
 #[no_mangle]
 pub unsafe extern "C" fn set_value(
     sim: &mut Simulation, 
@@ -90,18 +135,26 @@ pub unsafe extern "C" fn set_value(
     }
 }
 
+/// # Safety
+/// `vec` and `len` must come from `get_value`.
 #[no_mangle]
 pub unsafe extern "C" fn drop_bools(vec: *mut bool, len: usize) {
     let vec = Vec::from_raw_parts(vec, len, len);
     std::mem::drop(vec);
 }
 
+/// # Safety
+/// `vec` and `len` must come from `get_description`.
 #[no_mangle]
 pub unsafe extern "C" fn drop_chars(vec: *mut u8, len: usize) {
     let vec = Vec::from_raw_parts(vec, len, len);
     std::mem::drop(vec);
 }
 
+/// Describes the module or wire at `path`.
+///
+/// # Safety
+/// `path_ptr` must point to `path_len` bytes of UTF-8, the description must be freed with `drop_chars`.
 #[no_mangle]
 pub unsafe extern "C" fn get_description(
     sim: &Simulation, 
@@ -129,14 +182,39 @@ pub unsafe extern "C" fn get_description(
     len
 }
 
+/// Describes the wires that keep toggling, one per line, nothing unless the simulation is oscillating.
+///
+/// # Safety
+/// The description must be freed with `drop_chars`.
+#[no_mangle]
+pub unsafe extern "C" fn get_oscillations(sim: &Simulation, graph: &GraphModule, description_ptr: &mut *const u8) -> usize {
+    let mut result: String = crate::testbench::oscillations(graph, sim)
+        .iter()
+        .map(|o| format!("{}\n", o))
+        .collect();
+
+    result.shrink_to_fit();
+    *description_ptr = result.as_bytes().as_ptr();
+    let len = result.len();
+    std::mem::forget(result);
+    len
+}
+
+/// Sets the number of toggles of a single wire after which `simulate` stops as oscillating.
+#[no_mangle]
+pub extern "C" fn set_oscillation_threshold(sim: &mut Simulation, toggles: u64) {
+    sim.set_oscillation_threshold(toggles as usize);
+}
+
+/// Returns the width of the wire at `path`, or 0 if there is none.
+///
+/// # Safety
+/// `path_ptr` must point to `path_len` bytes of UTF-8.
 #[no_mangle]
 pub unsafe extern "C" fn get_width(graph: &GraphModule, path_ptr: *const u8, path_len: u64) -> u64 {
     let path = match path(path_ptr, path_len) {
         Ok((_, path)) => path,
-        Err(e) => {
-            format!("Error: {:?}", e);
-            return 0;
-        },
+        Err(_) => return 0,
     };
     graph.wire_width(&path).unwrap_or(0)
 }
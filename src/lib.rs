@@ -5,97 +5,356 @@ mod net;
 mod netgraph;
 mod link;
 mod interact;
+mod assertion;
+mod monitor;
+mod testbench;
+mod export;
+mod vectors;
+mod repl;
+mod reload;
+mod project;
+mod design;
+mod emit;
+mod format;
+mod lsp;
+mod lint;
+mod loops;
+pub mod builder;
+pub mod cli;
+
+#[cfg(test)]
+mod tests;
 
 use parsed::*;
 use parsing::*;
 use net::*;
 use netgraph::*;
 use link::*;
+use project::Project;
 pub use interact::*;
+pub use design::{Design, Simulator, Error};
+pub use parsed::{
+    Module, Wire, WireKind, Instance, Connection, Operation, WirePart, WireRange, WireBus,
+    Assertion, Property, Sequence,
+};
+pub use link::ErrorKind;
+pub use testbench::{TestResult, Oscillation};
 
 use std::collections::*;
-use std::fs::File;
-use std::io::prelude::*;
+use std::path::{Path, PathBuf};
 use walkdir::{WalkDir, DirEntry};
 
+/// modules that are available without being defined in the source files
+const STDLIB: &str = include_str!("stdlib.rva");
+
 fn is_source_file(entry: &DirEntry) -> bool {
     entry.file_type().is_file() && 
     entry.file_name().to_str().map(|s| s.ends_with(".rva")).unwrap_or(false)
 }
 
-fn read_source() -> String {
-    let mut source = String::new();
+/// reads all source files in the given files and directories, except for the excluded ones
+fn read_files<P: AsRef<Path>>(paths: &[P], exclude: &[PathBuf]) -> Result<Vec<(PathBuf, String)>, Error> {
+    let mut files = Vec::new();
+    for path in paths.iter() {
+        let path = path.as_ref();
+        let entries = WalkDir::new(path)
+            .into_iter()
+            .filter_entry(|e| !exclude.iter().any(|x| e.path().starts_with(x)));
+        for entry in entries {
+            let entry = entry.map_err(|e| Error::Io { path: e.path().unwrap_or(path).to_owned(), error: e.into() })?;
+            if is_source_file(&entry) {
+                let source = std::fs::read_to_string(entry.path())
+                    .map_err(|error| Error::Io { path: entry.path().to_owned(), error })?;
+                files.push((entry.path().to_owned(), source));
+            }
+        }
+    }
+    Ok(files)
+}
 
-    println!("Reading files: ");
-    for entry in WalkDir::new("."){
-        let entry = entry.unwrap();
-        if !is_source_file(&entry) {
-            continue;
+/// Parses the declarations of a source file, fails with the line of the first invalid one and why
+/// it is invalid. Only invalid wire declarations are explained, everything else is an invalid declaration.
+fn parse_declarations(source: &str) -> Result<Vec<Declaration>, (usize, String)> {
+    let line = |offset: usize| source[..offset].matches('\n').count() + 1;
+    match declarations(source) {
+        Ok((_, declarations)) => Ok(declarations),
+        Err(nom::Err::Error((rest, _))) | Err(nom::Err::Failure((rest, _))) => {
+            let start = source.len() - rest.len();
+            match declaration_error(rest) {
+                Some((offset, message)) => Err((line(start + offset), message)),
+                None => Err((line(start), String::from("invalid declaration"))),
+            }
+        },
+        // the declaration is cut off by the end of the file
+        Err(nom::Err::Incomplete(_)) => Err((source.lines().count().max(1), String::from("invalid declaration"))),
+    }
+}
+
+/// parses every file on its own, so that errors point to the file and line they occur in
+fn parse_files(files: &[(PathBuf, String)]) -> Result<(Vec<Module>, Vec<TestBench>), Error> {
+    let mut mods = Vec::new();
+    let mut tests = Vec::new();
+    for (path, source) in files.iter() {
+        let declarations = parse_declarations(source)
+            .map_err(|(line, message)| Error::Parse { path: path.clone(), line, message })?;
+        for declaration in declarations.into_iter() {
+            match declaration {
+                Declaration::Module(m) => mods.push(m),
+                Declaration::Test(t) => tests.push(t),
+            }
         }
+    }
+    Ok((mods, tests))
+}
+
+/// everything that is parsed from the files of a project
+struct Sources {
+    files: Vec<PathBuf>,
+    libraries: Vec<Module>,
+    modules: Vec<Module>,
+    tests: Vec<TestBench>,
+}
 
-        let mut file = File::open(entry.path()).unwrap();
-        file.read_to_string(&mut source).unwrap();
+fn load_sources(project: &Project) -> Result<Sources, Error> {
+    let files = read_files(&project.source_paths(), &project.excluded_paths())?;
+    let (modules, tests) = parse_files(&files)?;
 
-        println!("{}", entry.path().display());
+    let mut libraries = Vec::new();
+    for path in project.library_paths().iter() {
+        libraries.append(&mut parse_files(&read_files(&[path], &[])?)?.0);
     }
-    println!();
-    source
+
+    Ok(Sources {
+        files: files.into_iter().map(|(path, _)| path).collect(),
+        libraries,
+        modules,
+        tests,
+    })
 }
 
-fn parse(source: &String) -> Vec<Module> {
-    let (rest, mods) = modules(&source).unwrap();
-    if rest.len() > 0 {
+fn parse(source: &str) -> Vec<Module> {
+    let (rest, mods) = modules(source).unwrap();
+    if !rest.is_empty() {
         eprintln!("Warning: Not everything of the source file has been parsed:\n{}", rest);
     }
     mods
 }
 
-fn build(mods: Vec<Module>) -> LinkResult<(GraphModule, Simulation)> {
+/// runs every test against a fresh simulation of its module
+fn run_tests(libraries: &[Module], mods: &[Module], tests: &[TestBench]) -> Vec<testbench::TestResult> {
+    tests
+        .iter()
+        .map(|test| match build_with_libraries(libraries.to_vec(), mods.to_vec(), &test.module) {
+            Ok((graph, mut sim)) => testbench::run(test, &graph, &mut sim),
+            Err(e) => testbench::TestResult {
+                name: test.name.clone(),
+                module: test.module.clone(),
+                failures: vec![format!("failed to elaborate ({:?}): {}", e.kind, e.description)],
+            },
+        })
+        .collect()
+}
+
+fn build_with_libraries(libraries: Vec<Module>, mods: Vec<Module>, top: &str) -> LinkResult<(GraphModule, Simulation)> {
     let mut mod_map = HashMap::new();
+    for m in parse(STDLIB).into_iter() {
+        mod_map.insert(m.name.to_owned(), m);
+    }
+
+    // libraries replace the standard library, the first library defining a module wins
+    let mut library_defined = HashSet::new();
+    for m in libraries.into_iter() {
+        if library_defined.insert(m.name.to_owned()) {
+            mod_map.insert(m.name.to_owned(), m);
+        }
+    }
+
+    // user defined modules replace the ones from the libraries
+    let mut user_defined = HashSet::new();
     for m in mods.into_iter() {
         let name = m.name.to_owned();
-        if let Some(_) = mod_map.insert(name.to_owned(), m) {
-            panic!("Duplicate module name: {}", &name);
+        if !user_defined.insert(name.to_owned()) {
+            return LinkError::new(ErrorKind::DuplicateModule, format!("Duplicate module name: {}", &name));
         }
+        mod_map.insert(name, m);
     }
 
     let mut net = Net::new();
     let mut descent = Vec::new();
-    let mut wires = vec![vec![]; 3];
-    let top = mod_map.get("Top").expect("No 'Top' Module found");
+    let top = match mod_map.get(top) {
+        Some(m) => m,
+        None => return LinkError::new(
+            ErrorKind::UnknownModule,
+            format!("No top module with name '{}'.", top)
+        ),
+    };
+
+    // the I/O of the top module has no parent module, 
+    // so it is allocated here and exposed as primary inputs and outputs
+    let mut wires = vec![vec![]; top.locals.len()];
+    for (idx, wire) in top.locals.iter().enumerate() {
+        if wire.kind.is_port() {
+            let begin = net.allocate_wire(wire.width);
+            wires[idx] = (begin..begin+wire.width).collect();
+
+            // primary inputs start out with their default value
+            if let Some(default) = &wire.default {
+                if default.len() != wire.width {
+                    return LinkError::new(
+                        ErrorKind::MismatchedWireSize,
+                        format!(
+                            "Default of wire '{}' of module '{}' has a size of {}, expected {}.",
+                            wire.name, top.name, default.len(), wire.width
+                        )
+                    );
+                }
+                for (addr, &bit) in wires[idx].iter().zip(default.iter()) {
+                    net.set_value(*addr, bit);
+                }
+            }
+        }
+    }
 
     let mut linker = Linker::new(top, &mut wires, &mod_map, &mut descent, &mut net)?;
     let graph = linker.link()?;
 
-    let sim = Simulation::new(net);
+    if let Some(wire) = net.conflicting_driver() {
+        return LinkError::new(
+            ErrorKind::MultipleDrivers,
+            format!(
+                "wire '{}' is driven by a tri-state gate and another gate.",
+                graph.wire_path(wire).unwrap_or_else(|| wire.to_string())
+            )
+        );
+    }
+
+    let mut sim = Simulation::new(net);
+    sim.add_monitors(monitor::monitors(&graph));
 
     Ok((graph, sim))
 }
 
+/// rebuilds the design and carries the values of all wires that still exist over from the old simulation
+fn rebuild(
+    libraries: Vec<Module>, mods: Vec<Module>, top: &str, 
+    graph: &GraphModule, sim: &Simulation) 
+-> LinkResult<(GraphModule, Simulation, reload::ReloadReport)> 
+{
+    let (new_graph, mut new_sim) = build_with_libraries(libraries, mods, top)?;
+    let report = reload::transfer(graph, sim, &new_graph, &mut new_sim);
+    Ok((new_graph, new_sim, report))
+}
+
 #[repr(C)]
 pub struct GraphAndSimulation {
     graph: *mut GraphModule,
     sim: *mut Simulation,
 }
 
+/// the project in the working directory, which is where the FFI loads its sources from
+fn working_project() -> Result<Project, Error> {
+    Project::load(Path::new(".")).map_err(Error::Manifest)
+}
+
+/// Builds the simulation of the default top module of the project, 'Top' if the manifest does not name one.
 #[no_mangle]
 pub extern "C" fn create_graph_simulation() -> GraphAndSimulation {
-    let source = read_source();
-    let mods = parse(&source);
+    create_simulation(None)
+}
 
-    match build(mods) {
+/// Builds the simulation with the module named `top` as root, its I/O wires are left undriven.
+///
+/// # Safety
+/// `top_ptr` must point to `top_len` bytes of UTF-8.
+#[no_mangle]
+pub unsafe extern "C" fn create_top_simulation(top_ptr: *const u8, top_len: u64) -> GraphAndSimulation {
+    let top = std::slice::from_raw_parts(top_ptr, top_len as _);
+    create_simulation(Some(std::str::from_utf8(top).unwrap()))
+}
+
+/// Runs all tests in the source files, prints the results and returns whether all of them passed.
+#[no_mangle]
+pub extern "C" fn run_source_tests() -> bool {
+    let sources = match working_project().and_then(|project| load_sources(&project)) {
+        Ok(sources) => sources,
+        Err(e) => {
+            eprintln!("Failed to read sources: {}", e);
+            return false;
+        },
+    };
+
+    let results = run_tests(&sources.libraries, &sources.modules, &sources.tests);
+    for result in results.iter() {
+        println!("{}", result);
+    }
+    let passed = results.iter().filter(|r| r.passed()).count();
+    println!("\n{} passed, {} failed", passed, results.len() - passed);
+
+    passed == results.len()
+}
+
+/// Re-reads the sources and rebuilds the simulation of `top`, keeping the values of all wires that still exist.
+/// An empty `top` stands for the default top module of the project.
+/// On success the graph and simulation in `state` are replaced and the old ones are freed,
+/// otherwise `state` is left untouched and false is returned.
+///
+/// # Safety
+/// `top_ptr` must point to `top_len` bytes of UTF-8, `state` must have been created by `create_top_simulation`
+/// or `create_graph_simulation`.
+#[no_mangle]
+pub unsafe extern "C" fn reload_simulation(top_ptr: *const u8, top_len: u64, state: &mut GraphAndSimulation) -> bool {
+    let top = std::slice::from_raw_parts(top_ptr, top_len as _);
+    let top = std::str::from_utf8(top).unwrap();
+
+    let (project, sources) = match working_project().and_then(|p| load_sources(&p).map(|s| (p, s))) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("Failed to read sources: {}", e);
+            return false;
+        },
+    };
+    let top = if top.is_empty() { project.top() } else { top };
+
+    match rebuild(sources.libraries, sources.modules, top, &*state.graph, &*state.sim) {
+        Ok((graph, sim, report)) => {
+            println!("{}", report);
+            drop(Box::from_raw(state.graph));
+            drop(Box::from_raw(state.sim));
+            state.graph = Box::into_raw(Box::new(graph));
+            state.sim = Box::into_raw(Box::new(sim));
+            true
+        },
+        Err(e) => {
+            eprintln!("Failed to link modules ({:?}): {}", e.kind, e.description);
+            false
+        },
+    }
+}
+
+fn create_simulation(top: Option<&str>) -> GraphAndSimulation {
+    let (project, sources) = match working_project().and_then(|p| load_sources(&p).map(|s| (p, s))) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("Failed to read sources: {}", e);
+            std::process::exit(1);
+        },
+    };
+
+    println!("Reading files: ");
+    for path in sources.files.iter() {
+        println!("{}", path.display());
+    }
+    println!();
+
+    let top = top.unwrap_or_else(|| project.top());
+    match build_with_libraries(sources.libraries, sources.modules, top) {
         Ok((graph, sim)) => {
             let graph = Box::into_raw(Box::new(graph));
             let sim   = Box::into_raw(Box::new(sim));
 
-            let ptr = GraphAndSimulation {
+            GraphAndSimulation {
                 graph, sim
-            };
-
-            std::mem::forget(graph);
-            std::mem::forget(sim);
-
-            ptr
+            }
         },
         Err(e) => {
             eprintln!("Failed to link modules ({:?}): {}", e.kind, e.description);
@@ -103,4 +362,3 @@ pub extern "C" fn create_graph_simulation() -> GraphAndSimulation {
         },
     }
 }
-
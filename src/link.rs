@@ -4,7 +4,7 @@ use crate::net::*;
 
 use std::collections::*;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ErrorKind {
     Recursion,
     MismatchedWireSize,
@@ -15,6 +15,9 @@ pub enum ErrorKind {
     IncorrectWireKind,
     MultipleDrivers,
     NoDriver,
+    UnknownPrimitive,
+    UndeclaredPrimitive,
+    DuplicateModule,
 }
 
 #[derive(Debug)]
@@ -24,7 +27,7 @@ pub struct LinkError {
 }
 
 impl LinkError {
-    fn new<T>(kind: ErrorKind, description: String) -> LinkResult<T> {
+    pub fn new<T>(kind: ErrorKind, description: String) -> LinkResult<T> {
         Err(Self { description, kind })
     }
 }
@@ -91,12 +94,21 @@ impl<'a> Linker<'a> {
         Ok(())
     }
 
-    fn alloc_wirebus(&mut self, bus: &'a WireBus, io_type: WireKind) -> LinkResult<Vec<usize>> {
+    fn alloc_wirebus(&mut self, bus: &WireBus, io_type: WireKind) -> LinkResult<Vec<usize>> {
         let mut alloc_bus = Vec::new();
         for part in bus.iter() {
             match part {
                 WirePart::Local{name, range} => {
-                    if let Some((idx, wire)) = self.find_wire(&name) {
+                    if let Some((idx, wire)) = self.find_wire(name) {
+                        if io_type == WireKind::InOut && !wire.kind.is_bus() {
+                            return LinkError::new(
+                                ErrorKind::IncorrectWireKind,
+                                format!(
+                                    "In module '{}': Wire '{}' is connected to an inout port, but is not a bus.",
+                                    self.module.name, name
+                                )
+                            );
+                        }
                         let range = if let WireRange::Ranged{from, to} = range {
                             if from > to || *to >= wire.width {
                                 return LinkError::new(
@@ -127,21 +139,25 @@ impl<'a> Linker<'a> {
 
                 },
                 WirePart::Constant(constant) => {
-                    let begin_const = self.net.allocate_wire(constant.len());
-                    for (idx, &bit) in constant.iter().enumerate() {
-                        alloc_bus.push(begin_const+idx);
-                        self.net.set_value(begin_const+idx, bit);
-                    }
+                    alloc_bus.append(&mut self.alloc_constant(constant));
                 }
             }
         }
         Ok(alloc_bus)
     }
 
+    fn alloc_constant(&mut self, constant: &[bool]) -> Vec<usize> {
+        let begin = self.net.allocate_wire(constant.len());
+        for (idx, &bit) in constant.iter().enumerate() {
+            self.net.set_value(begin+idx, bit);
+        }
+        (begin..begin+constant.len()).collect()
+    }
+
     fn find_wire(&self, name: &String) -> Option<(usize, &Wire)> {
         for (idx, wire) in self.module.locals.iter().enumerate() {
             if &wire.name == name {
-                return Some((idx, &wire));
+                return Some((idx, wire));
             }
         }
         None
@@ -150,7 +166,7 @@ impl<'a> Linker<'a> {
     fn link_instance_io(
         &mut self, module: &'a Module, 
         instance: &'a Instance, 
-        allocated_wires: &mut Vec<Vec<usize>>, 
+        allocated_wires: &mut [Vec<usize>], 
         io_type: WireKind)
         -> LinkResult<()> 
     {
@@ -174,7 +190,9 @@ impl<'a> Linker<'a> {
                 };
             let child_wire = &module.locals[wire_idx];
 
-            if child_wire.kind != io_type {
+            // inout ports are bound together with the inputs
+            let is_inout = io_type == WireKind::Input && child_wire.kind == WireKind::InOut;
+            if child_wire.kind != io_type && !is_inout {
                 return LinkError::new(
                     ErrorKind::IncorrectWireKind, 
                     format!(
@@ -184,7 +202,25 @@ impl<'a> Linker<'a> {
                 );
             }
 
-            allocated_wires[wire_idx] = self.alloc_wirebus(&io_wire.local, io_type)?;
+            if io_type == WireKind::Input && io_wire.local.is_empty() {
+                return LinkError::new(
+                    ErrorKind::MissingIOWires,
+                    format!(
+                        "Module Instantiation '{}' in '{}': Input '{}' is bound to '_', only outputs can be discarded.",
+                        instance.name, self.module.name, wire_name
+                    )
+                );
+            }
+
+            if io_type == WireKind::Output && io_wire.local.is_empty() {
+                // discarded output, it still needs wires to be driven
+                let width = child_wire.width;
+                let begin = self.net.allocate_wire(width);
+                allocated_wires[wire_idx] = (begin..begin+width).collect();
+                continue;
+            }
+
+            allocated_wires[wire_idx] = self.alloc_wirebus(&io_wire.local, child_wire.kind)?;
 
             if allocated_wires[wire_idx].len() != module.locals[wire_idx].width {
                 return LinkError::new(
@@ -200,6 +236,74 @@ impl<'a> Linker<'a> {
         Ok(())
     }
 
+    /// finds the single bit port of a primitive by name
+    fn primitive_port(&self, name: &str) -> LinkResult<usize> {
+        match self.module.locals.iter().position(|w| w.name == name) {
+            Some(idx) if self.module.locals[idx].width == 1 => Ok(self.allocated_wires[idx][0]),
+            Some(_) => LinkError::new(
+                ErrorKind::MismatchedWireSize,
+                format!(
+                    "Port '{}' of primitive '{}' needs to be a single bit.",
+                    name, self.module.name
+                )
+            ),
+            None => LinkError::new(
+                ErrorKind::UnknownWire,
+                format!(
+                    "Primitive '{}' has no port with name '{}'.",
+                    self.module.name, name
+                )
+            ),
+        }
+    }
+
+    fn link_primitive(&mut self) -> LinkResult<GraphModule> {
+        match self.module.name.as_str() {
+            "Nor" => {
+                let a = self.primitive_port("a")?;
+                let b = self.primitive_port("b")?;
+                let out = self.primitive_port("out")?;
+                self.net.create_nor(a, b, out);
+            },
+            "TriState" => {
+                let input = self.primitive_port("in")?;
+                let enable = self.primitive_port("en")?;
+                let out = self.primitive_port("out")?;
+                self.net.create_tristate(input, enable, out);
+            },
+            _ => return LinkError::new(
+                ErrorKind::UnknownPrimitive,
+                format!(
+                    "There is no native gate for primitive '{}'.",
+                    self.module.name
+                )
+            ),
+        }
+
+        Ok(
+            GraphModule {
+                module_name: self.module.name.clone(),
+                name: String::from("<primitive>"),
+                latch: false,
+                instances: Vec::new(),
+                assertions: Vec::new(),
+                properties: Vec::new(),
+                locals:
+                    self.module.locals
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, wire)|
+                        GraphWire {
+                            name: wire.name.clone(),
+                            kind: wire.kind,
+                            values: self.allocated_wires[idx].clone(),
+                        }
+                    )
+                    .collect()
+            }
+        )
+    }
+
     pub fn link(&mut self) -> LinkResult<GraphModule> {
         if self.descent.contains(&self.module.name) {
             return LinkError::new(
@@ -211,44 +315,72 @@ impl<'a> Linker<'a> {
             );
         }
 
-        // base case, everything gets broken down to nor gates
-        if self.module.name == "Nor" {
-            let a = self.allocated_wires[0][0];
-            let b = self.allocated_wires[1][0];
-            let out = self.allocated_wires[2][0];
-            self.net.create_nor(a, b, out);
-
-            let graph_wire = |name, idx| GraphWire {
-                name: String::from(name),
-                values: vec![idx],
-            };
+        // base case, everything gets broken down to primitives
+        if self.module.primitive {
+            return self.link_primitive();
+        }
 
-            return Ok(
-                GraphModule {
-                    module_name: self.module.name.clone(),
-                    name: String::from("<nor>"),
-                    instances: Vec::new(),
-                    locals: vec![
-                        graph_wire("a", a),
-                        graph_wire("b", b),
-                        graph_wire("out", out),
-                    ],
-                }
+        // a module without instances whose outputs nothing drives was most likely meant as a primitive,
+        // modules that only contain assertions or properties are monitors, which do not need any gates
+        let monitor = !self.module.assertions.is_empty() || !self.module.properties.is_empty();
+        let has_outputs = self.module.locals.iter().any(|w| w.kind == WireKind::Output);
+        if self.module.instances.is_empty() && has_outputs && !monitor {
+            return LinkError::new(
+                ErrorKind::UndeclaredPrimitive,
+                format!(
+                    "Module '{}' has no instances, but is not declared as a primitive.",
+                    self.module.name
+                )
             );
         }
 
         for (idx, wire) in self.module.locals.iter().enumerate() {
-            if wire.kind == WireKind::Private {
+            if !wire.kind.is_port() {
                 // allocate space only for private wires, 
                 // I/O is already allocated by the parent module
 
                 let begin = self.net.allocate_wire(wire.width);
                 self.allocated_wires[idx] = (begin..begin+wire.width).collect();
+
+                if let Some(init) = &wire.init {
+                    if init.len() != wire.width {
+                        return LinkError::new(
+                            ErrorKind::MismatchedWireSize,
+                            format!(
+                                "Initial value of wire '{}' in module '{}' has a size of {}, expected {}.",
+                                wire.name, self.module.name, init.len(), wire.width
+                            )
+                        );
+                    }
+                    for (i, &bit) in init.iter().enumerate() {
+                        self.net.init_value(begin+i, bit);
+                    }
+                }
             } else {
                 assert_eq!(self.allocated_wires[idx].len(), wire.width);
             }
         }
 
+        for assertion in self.module.assertions.iter() {
+            let operations = assertion.enable.iter().chain(Some(&assertion.condition));
+            for op in operations {
+                if let Err(e) = crate::assertion::validate(op, self.module) {
+                    return LinkError::new(
+                        ErrorKind::UnknownWire,
+                        format!("In assertion '{}' in module '{}': {}", assertion.source, self.module.name, e)
+                    );
+                }
+            }
+        }
+
+        let mut properties = Vec::new();
+        for property in self.module.properties.iter() {
+            let property = property.clone().try_map(&mut |bus| {
+                self.alloc_wirebus(&bus, WireKind::Input).map(|addr| addr[0])
+            })?;
+            properties.push(property);
+        }
+
         let mut graph_instances = Vec::new();
 
         for instance in self.module.instances.iter() {
@@ -271,10 +403,23 @@ impl<'a> Linker<'a> {
 
             // check if all I/O has been assigned
             for (i, wire) in module.locals.iter().enumerate() {
-                if wire.kind == WireKind::Private {
+                if !wire.kind.is_port() {
                     assert_eq!(allocated_wires[i].len(), 0);
                 } else {
-                    if allocated_wires[i].len() == 0 {
+                    if allocated_wires[i].is_empty() {
+                        if let Some(default) = &wire.default {
+                            if default.len() != wire.width {
+                                return LinkError::new(
+                                    ErrorKind::MismatchedWireSize,
+                                    format!(
+                                        "Default of wire '{}' of module '{}' has a size of {}, expected {}.",
+                                        wire.name, module.name, default.len(), wire.width
+                                    )
+                                );
+                            }
+                            allocated_wires[i] = self.alloc_constant(default);
+                            continue;
+                        }
                         return LinkError::new(
                             ErrorKind::MissingIOWires, 
                             format!(
@@ -301,6 +446,11 @@ impl<'a> Linker<'a> {
         }
 
         for (wire_idx, wire) in self.module.locals.iter().enumerate() {
+            if wire.kind.is_bus() {
+                // buses may have any number of tri-state drivers, 
+                // conflicts with other gates are checked on the whole net
+                continue;
+            }
             for (bit_idx, &bit) in self.wire_edits[wire_idx].iter().enumerate() {
                 if bit > 1 {
                     return LinkError::new(
@@ -326,7 +476,10 @@ impl<'a> Linker<'a> {
             GraphModule{
                 module_name: self.module.name.clone(),
                 name: String::from("<root>"),
+                latch: self.module.latch,
                 instances: graph_instances,
+                assertions: self.module.assertions.clone(),
+                properties,
                 locals: 
                     self.module.locals
                     .iter()
@@ -334,6 +487,7 @@ impl<'a> Linker<'a> {
                     .map(|(idx, wire)| 
                         GraphWire {
                             name: wire.name.clone(),
+                            kind: wire.kind,
                             values: self.allocated_wires[idx].clone(),
                         }
                     )
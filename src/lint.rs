@@ -0,0 +1,343 @@
+use crate::parsed::*;
+use crate::netgraph::GraphModule;
+use crate::net::{Gate, Simulation};
+use crate::project::LintSettings;
+
+use serde::Deserialize;
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Lint {
+    /// a private wire or bus that is never read
+    UnusedWire,
+    /// an input that is never read inside of its module
+    UnusedInput,
+    /// an output that is only driven by instances with constant inputs
+    ConstantOutput,
+    /// a module of the sources that is neither instanced from the top nor tested
+    UnusedModule,
+    /// an instance that discards all of its outputs
+    DiscardedInstance,
+    /// a bit that drives more gate inputs than the limit
+    ExcessiveFanout,
+    /// a name that looks like the ones generated for assignments, `gen_` followed by a number
+    GeneratedName,
+}
+
+pub const LINTS: [Lint; 7] = [
+    Lint::UnusedWire,
+    Lint::UnusedInput,
+    Lint::ConstantOutput,
+    Lint::UnusedModule,
+    Lint::DiscardedInstance,
+    Lint::ExcessiveFanout,
+    Lint::GeneratedName,
+];
+
+impl Lint {
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::UnusedWire => "unused-wire",
+            Lint::UnusedInput => "unused-input",
+            Lint::ConstantOutput => "constant-output",
+            Lint::UnusedModule => "unused-module",
+            Lint::DiscardedInstance => "discarded-instance",
+            Lint::ExcessiveFanout => "excessive-fanout",
+            Lint::GeneratedName => "generated-name",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Lint> {
+        LINTS.iter().copied().find(|l| l.name() == name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Allow,
+    Warn,
+    /// the lint fails the check
+    Deny,
+}
+
+/// number of gate inputs a bit may drive if the manifest sets no other limit
+pub const MAX_FANOUT: usize = 64;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    levels: HashMap<Lint, Level>,
+    pub max_fanout: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config { levels: HashMap::new(), max_fanout: MAX_FANOUT }
+    }
+}
+
+impl Config {
+    /// the settings of a manifest, which may only name known lints
+    pub fn from_settings(settings: &LintSettings) -> Result<Config, String> {
+        let mut config = Config::default();
+        for (name, level) in settings.levels.iter() {
+            config.set(name, *level)?;
+        }
+        config.max_fanout = settings.max_fanout.unwrap_or(MAX_FANOUT);
+        Ok(config)
+    }
+
+    pub fn set(&mut self, name: &str, level: Level) -> Result<(), String> {
+        let lint = Lint::from_name(name).ok_or_else(|| format!("unknown lint '{}'", name))?;
+        self.levels.insert(lint, level);
+        Ok(())
+    }
+
+    /// lints warn unless configured otherwise
+    pub fn level(&self, lint: Lint) -> Level {
+        self.levels.get(&lint).copied().unwrap_or(Level::Warn)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    pub lint: Lint,
+    pub level: Level,
+    /// module the warning is about, the top module for lints of the linked design
+    pub module: String,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = if self.level == Level::Deny { "error" } else { "warning" };
+        write!(f, "{}[{}]: {}: {}", severity, self.lint.name(), self.module, self.message)
+    }
+}
+
+fn is_generated(name: &str) -> bool {
+    name.strip_prefix("gen_").map(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit())).unwrap_or(false)
+}
+
+fn bus_names<'a>(bus: &'a [WirePart], names: &mut HashSet<&'a str>) {
+    for part in bus.iter() {
+        if let WirePart::Local { name, .. } = part {
+            names.insert(name);
+        }
+    }
+}
+
+fn operation_names<'a>(op: &'a Operation, names: &mut HashSet<&'a str>) {
+    match op {
+        Operation::Wire(bus) => bus_names(bus, names),
+        Operation::And(a, b) | Operation::Or(a, b) | Operation::Xor(a, b) | Operation::Eq(a, b) => {
+            operation_names(a, names);
+            operation_names(b, names);
+        },
+        Operation::AndReduce(op) | Operation::OrReduce(op) | Operation::XorReduce(op) | Operation::Not(op) => {
+            operation_names(op, names)
+        },
+    }
+}
+
+/// wires that are read by an instance, an assertion or a property
+fn reads(module: &Module) -> HashSet<&str> {
+    let mut names = HashSet::new();
+    for inst in module.instances.iter() {
+        for connection in inst.inputs.iter() {
+            bus_names(&connection.local, &mut names);
+        }
+    }
+    for assertion in module.assertions.iter() {
+        operation_names(&assertion.condition, &mut names);
+        if let Some(enable) = &assertion.enable {
+            operation_names(enable, &mut names);
+        }
+    }
+    for property in module.properties.iter() {
+        bus_names(&property.clock, &mut names);
+        if let Some(antecedent) = &property.antecedent {
+            bus_names(antecedent, &mut names);
+        }
+        let mut sequence = &property.consequent;
+        loop {
+            match sequence {
+                Sequence::Holds(s) | Sequence::Within(_, s) => bus_names(s, &mut names),
+                Sequence::Until(s1, s2) => {
+                    bus_names(s1, &mut names);
+                    bus_names(s2, &mut names);
+                },
+                Sequence::Next(next) => {
+                    sequence = next;
+                    continue;
+                },
+            }
+            break;
+        }
+    }
+    names
+}
+
+/// Lints of a single module, `modules` are all modules the instances may refer to.
+/// Assignments are already lowered to instances, so they count as reads and drivers.
+pub fn lint_module(module: &Module, modules: &HashMap<&str, &Module>) -> Vec<(Lint, String)> {
+    let mut found = Vec::new();
+    if module.primitive {
+        return found;
+    }
+
+    let read = reads(module);
+    let mut driven = HashSet::new();
+    for inst in module.instances.iter() {
+        for connection in inst.outputs.iter() {
+            bus_names(&connection.local, &mut driven);
+        }
+    }
+    for wire in module.locals.iter().filter(|w| !is_generated(&w.name) && !read.contains(w.name.as_str())) {
+        match wire.kind {
+            WireKind::Private | WireKind::Bus if driven.contains(wire.name.as_str()) => {
+                found.push((Lint::UnusedWire, format!("wire '{}' is never read", wire.name)));
+            },
+            WireKind::Private | WireKind::Bus => {
+                found.push((Lint::UnusedWire, format!("wire '{}' is never used", wire.name)));
+            },
+            WireKind::Input => found.push((Lint::UnusedInput, format!("input '{}' is never read", wire.name))),
+            _ => {},
+        }
+    }
+
+    for output in module.locals.iter().filter(|w| w.kind == WireKind::Output) {
+        let drivers: Vec<&Instance> = module.instances
+            .iter()
+            .filter(|i| i.outputs.iter().any(|c| {
+                c.local.iter().any(|p| matches!(p, WirePart::Local { name, .. } if *name == output.name))
+            }))
+            .collect();
+        let constant = |i: &&Instance| {
+            i.inputs.iter().all(|c| c.local.iter().all(|p| matches!(p, WirePart::Constant(_))))
+        };
+        if !drivers.is_empty() && drivers.iter().all(constant) {
+            found.push((Lint::ConstantOutput, format!("output '{}' is only driven by constants", output.name)));
+        }
+    }
+
+    for inst in module.instances.iter() {
+        let instanced = match modules.get(inst.module.as_str()) {
+            Some(m) => m,
+            None => continue,
+        };
+        // instances that drive a bus are used even without outputs
+        let has_outputs = instanced.locals.iter().any(|w| w.kind == WireKind::Output);
+        let drives_bus = instanced.locals.iter().any(|w| w.kind == WireKind::InOut);
+        if has_outputs && !drives_bus && inst.outputs.iter().all(|c| c.local.is_empty()) {
+            found.push((
+                Lint::DiscardedInstance,
+                format!("the outputs of instance '{}' of '{}' are all discarded", inst.name, inst.module),
+            ));
+        }
+    }
+
+    // the generator numbers its names from 0 without gaps, so names that occur twice
+    // or after a gap come from the source
+    let mut numbers: BTreeMap<u64, usize> = BTreeMap::new();
+    let names = module.locals.iter().map(|w| &w.name).chain(module.instances.iter().map(|i| &i.name));
+    for name in names.filter(|n| is_generated(n)) {
+        *numbers.entry(name[4..].parse().unwrap_or(u64::MAX)).or_insert(0) += 1;
+    }
+    let generated = (0..).take_while(|n| numbers.contains_key(n)).count() as u64;
+    for (number, count) in numbers.iter() {
+        if *count > 1 {
+            found.push((Lint::GeneratedName, format!("'gen_{}' is also a name generated for an assignment", number)));
+        } else if *number >= generated {
+            found.push((Lint::GeneratedName, format!("'gen_{}' may collide with the names generated for assignments", number)));
+        }
+    }
+    found
+}
+
+fn instanced_modules<'a>(graph: &'a GraphModule, names: &mut HashSet<&'a str>) {
+    names.insert(&graph.module_name);
+    for inst in graph.instances.iter() {
+        instanced_modules(inst, names);
+    }
+}
+
+/// Lints of the linked design. `modules` are the modules of the sources, `tested` the ones
+/// that testbenches elaborate.
+pub fn lint_design(
+    graph: &GraphModule, sim: &Simulation, modules: &[Module], tested: &[&str], config: &Config,
+) -> Vec<(Lint, String)> {
+    let mut found = Vec::new();
+
+    let mut instanced = HashSet::new();
+    instanced_modules(graph, &mut instanced);
+    for module in modules.iter() {
+        let name = module.name.as_str();
+        if !instanced.contains(name) && !tested.contains(&name) {
+            found.push((Lint::UnusedModule, format!("module '{}' is never instanced from '{}'", name, graph.module_name)));
+        }
+    }
+
+    let mut fanout = vec![0usize; sim.net().wire_count()];
+    for gate in sim.net().gates().iter() {
+        match *gate {
+            Gate::Nor { in1, in2, .. } => {
+                fanout[in1] += 1;
+                if in2 != in1 {
+                    fanout[in2] += 1;
+                }
+            },
+            Gate::TriState { input, enable, .. } => {
+                fanout[input] += 1;
+                fanout[enable] += 1;
+            },
+        }
+    }
+    // the bits of a wire are reported together with the highest fanout among them
+    let names = graph.wire_names();
+    let mut wires: BTreeMap<&str, usize> = BTreeMap::new();
+    for (addr, &count) in fanout.iter().enumerate().filter(|(_, &c)| c > config.max_fanout) {
+        if let Some(name) = names.get(&addr) {
+            let wire = name.rsplit_once('[').map(|(wire, _)| wire).unwrap_or(name);
+            let max = wires.entry(wire).or_insert(0);
+            *max = (*max).max(count);
+        }
+    }
+    for (wire, count) in wires.into_iter() {
+        found.push((
+            Lint::ExcessiveFanout,
+            format!("'{}' drives up to {} gate inputs, more than {}", wire, count, config.max_fanout),
+        ));
+    }
+    found
+}
+
+/// Runs all lints over the modules of the sources and the linked design, allowed lints are left out.
+pub fn lint(
+    config: &Config, libraries: &[Module], modules: &[Module], tests: &[TestBench], graph: &GraphModule, sim: &Simulation,
+) -> Vec<Warning> {
+    let mut all: HashMap<&str, &Module> = HashMap::new();
+    // later modules replace earlier ones the same way as when linking
+    let stdlib = crate::parse(crate::STDLIB);
+    for m in stdlib.iter().chain(libraries.iter().rev()).chain(modules.iter()) {
+        all.insert(&m.name, m);
+    }
+
+    let mut warnings = Vec::new();
+    let mut push = |module: &str, found: Vec<(Lint, String)>| {
+        for (lint, message) in found.into_iter() {
+            let level = config.level(lint);
+            if level != Level::Allow {
+                warnings.push(Warning { lint, level, module: module.to_string(), message });
+            }
+        }
+    };
+    for module in modules.iter() {
+        push(&module.name, lint_module(module, &all));
+    }
+    let tested: Vec<&str> = tests.iter().map(|t| t.module.as_str()).collect();
+    push(&graph.module_name, lint_design(graph, sim, modules, &tested, config));
+    warnings
+}
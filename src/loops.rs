@@ -0,0 +1,124 @@
+use crate::net::{Gate, Net};
+use crate::netgraph::GraphModule;
+
+use std::collections::HashSet;
+
+/// for every wire the wires it drives through a gate
+fn successors(net: &Net) -> Vec<Vec<usize>> {
+    let mut edges = vec![Vec::new(); net.wire_count()];
+    for gate in net.gates().iter() {
+        let (a, b, out) = match *gate {
+            Gate::Nor { in1, in2, out } => (in1, in2, out),
+            Gate::TriState { input, enable, out } => (input, enable, out),
+        };
+        edges[a].push(out);
+        if b != a {
+            edges[b].push(out);
+        }
+    }
+    edges
+}
+
+/// Strongly connected components of the wire graph with Tarjan's algorithm. The recursion is
+/// replaced by an explicit stack, since a long chain of gates would overflow the call stack.
+fn components(edges: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    const UNVISITED: usize = usize::MAX;
+    let mut index = vec![UNVISITED; edges.len()];
+    let mut lowlink = vec![0; edges.len()];
+    let mut on_stack = vec![false; edges.len()];
+    let mut stack = Vec::new();
+    let mut next_index = 0;
+    let mut found = Vec::new();
+
+    for root in 0..edges.len() {
+        if index[root] != UNVISITED {
+            continue;
+        }
+        // wires that are being visited and the position of the next successor to look at
+        let mut calls = vec![(root, 0)];
+        while let Some(&mut (wire, ref mut position)) = calls.last_mut() {
+            if *position == 0 {
+                index[wire] = next_index;
+                lowlink[wire] = next_index;
+                next_index += 1;
+                stack.push(wire);
+                on_stack[wire] = true;
+            }
+            if let Some(&next) = edges[wire].get(*position) {
+                *position += 1;
+                if index[next] == UNVISITED {
+                    calls.push((next, 0));
+                } else if on_stack[next] {
+                    lowlink[wire] = lowlink[wire].min(index[next]);
+                }
+                continue;
+            }
+
+            calls.pop();
+            if let Some(&(caller, _)) = calls.last() {
+                lowlink[caller] = lowlink[caller].min(lowlink[wire]);
+            }
+            if lowlink[wire] == index[wire] {
+                let mut component = Vec::new();
+                loop {
+                    let member = stack.pop().unwrap();
+                    on_stack[member] = false;
+                    component.push(member);
+                    if member == wire {
+                        break;
+                    }
+                }
+                found.push(component);
+            }
+        }
+    }
+    found
+}
+
+fn addresses(graph: &GraphModule, addresses: &mut HashSet<usize>) {
+    for wire in graph.locals.iter() {
+        addresses.extend(wire.values.iter().copied());
+    }
+    for inst in graph.instances.iter() {
+        self::addresses(inst, addresses);
+    }
+}
+
+/// the wires of every instance of a module declared with `@latch`
+fn latches(graph: &GraphModule, found: &mut Vec<HashSet<usize>>) {
+    if graph.latch {
+        let mut wires = HashSet::new();
+        addresses(graph, &mut wires);
+        found.push(wires);
+    } else {
+        for inst in graph.instances.iter() {
+            latches(inst, found);
+        }
+    }
+}
+
+/// Finds the combinational loops of the net, which are the wires that drive themselves through
+/// gates. Loops that lie within a single `@latch` instance are intended and left out.
+/// Every loop is a sorted list of the hierarchical names of its bits.
+pub fn find(net: &Net, graph: &GraphModule) -> Vec<Vec<String>> {
+    let edges = successors(net);
+    let mut intended = Vec::new();
+    latches(graph, &mut intended);
+    let names = graph.wire_names();
+
+    let mut loops: Vec<Vec<String>> = components(&edges)
+        .into_iter()
+        .filter(|c| c.len() > 1 || edges[c[0]].contains(&c[0]))
+        .filter(|c| !intended.iter().any(|latch| c.iter().all(|addr| latch.contains(addr))))
+        .map(|c| {
+            let mut wires: Vec<String> = c
+                .iter()
+                .map(|addr| names.get(addr).cloned().unwrap_or_else(|| format!("<wire {}>", addr)))
+                .collect();
+            wires.sort();
+            wires
+        })
+        .collect();
+    loops.sort();
+    loops
+}
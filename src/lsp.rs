@@ -0,0 +1,615 @@
+use crate::parsed::*;
+use crate::parsing::Declaration;
+use crate::format::{lex, Kind, Token};
+use crate::emit::Header;
+use crate::project::Project;
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument,
+    Notification as _, PublishDiagnostics,
+};
+use lsp_types::request::{Completion, DocumentSymbolRequest, GotoDefinition, HoverRequest, Request as _};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse, Diagnostic,
+    DiagnosticSeverity, DocumentSymbol, DocumentSymbolParams, DocumentSymbolResponse, GotoDefinitionParams,
+    GotoDefinitionResponse, Hover, HoverContents, HoverParams, HoverProviderCapability, InitializeParams,
+    Location, MarkupContent, MarkupKind, OneOf, Position, PublishDiagnosticsParams, Range, ServerCapabilities,
+    SymbolKind, TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+/// byte offsets of the start and end of a part of a source
+type Span = (usize, usize);
+
+fn position(text: &str, offset: usize) -> Position {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map(|idx| idx + 1).unwrap_or(0);
+    Position::new(before.matches('\n').count() as u32, before[line_start..].encode_utf16().count() as u32)
+}
+
+/// byte offset of the position, columns are counted in UTF-16 code units
+fn offset(text: &str, position: Position) -> usize {
+    let mut line_start = 0;
+    for _ in 0..position.line {
+        match text[line_start..].find('\n') {
+            Some(idx) => line_start += idx + 1,
+            None => return text.len(),
+        }
+    }
+    let line = text[line_start..].split('\n').next().unwrap_or("");
+    let mut units = 0;
+    for (idx, c) in line.char_indices() {
+        if units >= position.character as usize {
+            return line_start + idx;
+        }
+        units += c.len_utf16();
+    }
+    line_start + line.len()
+}
+
+fn range(text: &str, span: Span) -> Range {
+    Range::new(position(text, span.0), position(text, span.1))
+}
+
+fn span(token: &Token) -> Span {
+    (token.offset, token.offset + token.text.len())
+}
+
+/// where a module and its wires are declared in a source file
+struct Outline {
+    module: Module,
+    /// the name in the declaration
+    name: Span,
+    /// the whole declaration
+    span: Span,
+    /// first occurrence of every wire that is written in the source, generated wires are left out
+    wires: Vec<(usize, Span)>,
+}
+
+/// a parsed source file
+struct Document<'a> {
+    /// all tokens except for comments
+    tokens: Vec<Token<'a>>,
+    outlines: Vec<Outline>,
+}
+
+impl<'a> Document<'a> {
+    /// fails with the line of the first invalid declaration and why it is invalid
+    fn parse(text: &'a str) -> Result<Self, (usize, String)> {
+        let mut modules: Vec<Module> = crate::parse_declarations(text)?
+            .into_iter()
+            .filter_map(|d| match d {
+                Declaration::Module(m) => Some(m),
+                Declaration::Test(_) => None,
+            })
+            .collect();
+        let tokens: Vec<Token> = lex(text).into_iter().filter(|t| t.kind != Kind::Comment).collect();
+
+        // declarations end with a `;` or the brace closing their body
+        let mut outlines = Vec::new();
+        let mut start = None;
+        let mut braces = 0;
+        for (idx, token) in tokens.iter().enumerate() {
+            // attributes like `@latch` come before the keyword
+            let attribute = idx > 0 && tokens[idx - 1].is("@");
+            if braces == 0 && start.is_none() && token.kind == Kind::Word && !attribute {
+                start = Some(idx);
+            }
+            let end = match token.text {
+                "{" if token.kind == Kind::Punct => { braces += 1; false },
+                "}" if token.kind == Kind::Punct => { braces -= 1; braces == 0 },
+                ";" => braces == 0,
+                _ => false,
+            };
+            if !end {
+                continue;
+            }
+            let first = start.take().unwrap_or(idx);
+            if !matches!(tokens[first].text, "module" | "primitive") || first + 1 >= tokens.len() {
+                continue;
+            }
+            let name = &tokens[first + 1];
+            let module = match modules.iter().position(|m| m.name == name.text) {
+                Some(position) => modules.remove(position),
+                None => continue,
+            };
+            let declaration = &tokens[first..=idx];
+            let wires = module.locals
+                .iter()
+                .enumerate()
+                .filter_map(|(local, wire)| {
+                    declaration
+                        .iter()
+                        .skip(2)
+                        .find(|t| t.kind == Kind::Word && t.text == wire.name)
+                        .map(|t| (local, span(t)))
+                })
+                .collect();
+            outlines.push(Outline { module, name: span(name), span: (tokens[first].offset, token.offset + 1), wires });
+        }
+        Ok(Document { tokens, outlines })
+    }
+
+    /// index of the word at the offset, which may be right behind it
+    fn word_at(&self, offset: usize) -> Option<usize> {
+        self.tokens
+            .iter()
+            .position(|t| t.kind == Kind::Word && t.offset <= offset && offset <= t.offset + t.text.len())
+    }
+
+    fn outline_at(&self, offset: usize) -> Option<&Outline> {
+        self.outlines.iter().find(|o| o.span.0 <= offset && offset <= o.span.1)
+    }
+
+    /// If the tokens before `idx` end inside of the port list of an instance, the name of the
+    /// instanced module, whether it is the list of outputs and the index of its opening parenthesis.
+    fn binding_list(&self, idx: usize) -> Option<(&'a str, bool, usize)> {
+        let tokens = &self.tokens;
+        let mut depth = 0;
+        let mut open = None;
+        for i in (0..idx).rev() {
+            match tokens[i].text {
+                ")" | "}" => depth += 1,
+                "(" | "{" if depth > 0 => depth -= 1,
+                "(" => {
+                    open = Some(i);
+                    break;
+                },
+                ";" | "{" => return None,
+                _ => {},
+            }
+        }
+        let open = open?;
+
+        let (inputs_open, outputs) = if open > 0 && tokens[open - 1].is("->") {
+            // the inputs are the list before the arrow
+            let mut depth = 0;
+            let mut inputs_open = None;
+            for i in (0..open.saturating_sub(1)).rev() {
+                match tokens[i].text {
+                    ")" => depth += 1,
+                    "(" if depth > 1 => depth -= 1,
+                    "(" => {
+                        inputs_open = Some(i);
+                        break;
+                    },
+                    _ => {},
+                }
+            }
+            (inputs_open?, true)
+        } else {
+            (open, false)
+        };
+
+        if inputs_open < 2 {
+            return None;
+        }
+        let (module, name) = (&tokens[inputs_open - 2], &tokens[inputs_open - 1]);
+        let statement_start = inputs_open == 2 || matches!(tokens[inputs_open - 3].text, ";" | "{" | "}");
+        if module.kind != Kind::Word || name.kind != Kind::Word || !statement_start
+            || matches!(module.text, "module" | "primitive") {
+            return None;
+        }
+        Some((module.text, outputs, open))
+    }
+
+    /// whether the token at `idx` is where the name of a port goes in a port list
+    fn is_port_position(&self, idx: usize) -> bool {
+        idx > 0 && (self.tokens[idx - 1].is("(") || self.tokens[idx - 1].is(","))
+    }
+}
+
+/// a module declaration found in the sources, the standard library has no location
+struct Found {
+    module: Module,
+    location: Option<Location>,
+    wires: Vec<(usize, Location)>,
+}
+
+fn describe(wire: &Wire) -> String {
+    let kind = match wire.kind {
+        WireKind::Private => "wire",
+        WireKind::Input => "input",
+        WireKind::Output => "output",
+        WireKind::Bus => "bus",
+        WireKind::InOut => "inout",
+    };
+    if wire.width == 1 {
+        format!("{} {}", kind, wire.name)
+    } else {
+        format!("{} {}[{}]", kind, wire.name, wire.width)
+    }
+}
+
+fn hover(text: String) -> Hover {
+    Hover {
+        contents: HoverContents::Markup(MarkupContent { kind: MarkupKind::Markdown, value: format!("```rva\n{}\n```", text) }),
+        range: None,
+    }
+}
+
+/// State of the language server: the files of the project and the documents that are open in the editor.
+#[derive(Default)]
+pub struct Server {
+    /// sources and libraries of the project as they are on disk
+    files: BTreeMap<Url, String>,
+    /// library files in the order of the manifest
+    libraries: Vec<Url>,
+    /// documents that are open, their text replaces the file on disk
+    documents: BTreeMap<Url, String>,
+}
+
+impl Server {
+    /// reads the sources and libraries of the project in the directory
+    pub fn new(root: Option<&Path>) -> Self {
+        let mut server = Server::default();
+        let project = match root.map(Project::load) {
+            Some(Ok(project)) => project,
+            Some(Err(e)) => {
+                eprintln!("error: {}", e);
+                return server;
+            },
+            None => return server,
+        };
+
+        let mut read = |paths: &[std::path::PathBuf], exclude: &[std::path::PathBuf], library: bool| {
+            match crate::read_files(paths, exclude) {
+                Ok(files) => {
+                    for (path, text) in files.into_iter() {
+                        if let Ok(url) = Url::from_file_path(std::path::absolute(&path).unwrap_or(path)) {
+                            if library {
+                                server.libraries.push(url.clone());
+                            }
+                            server.files.insert(url, text);
+                        }
+                    }
+                },
+                Err(e) => eprintln!("error: {}", e),
+            }
+        };
+        read(&project.source_paths(), &project.excluded_paths(), false);
+        for path in project.library_paths().iter() {
+            read(std::slice::from_ref(path), &[], true);
+        }
+        server
+    }
+
+    pub fn open(&mut self, url: Url, text: String) {
+        self.documents.insert(url, text);
+    }
+
+    /// the document is read from disk again if it belongs to the project
+    pub fn close(&mut self, url: &Url) {
+        self.documents.remove(url);
+        if self.files.contains_key(url) {
+            if let Some(text) = url.to_file_path().ok().and_then(|path| std::fs::read_to_string(path).ok()) {
+                self.files.insert(url.clone(), text);
+            }
+        }
+    }
+
+    fn text(&self, url: &Url) -> Option<&str> {
+        self.documents.get(url).or_else(|| self.files.get(url)).map(String::as_str)
+    }
+
+    /// all sources and libraries, the open documents replace the files on disk
+    fn sources(&self) -> Vec<(&Url, &str, bool)> {
+        let mut sources: Vec<_> = self.files
+            .keys()
+            .chain(self.documents.keys().filter(|url| !self.files.contains_key(url)))
+            .map(|url| (url, self.text(url).unwrap_or_default(), self.libraries.contains(url)))
+            .collect();
+        // libraries keep the order of the manifest, as earlier ones take precedence
+        sources.sort_by_key(|(url, _, library)| (*library, self.libraries.iter().position(|l| l == *url)));
+        sources
+    }
+
+    /// the module with the name in the sources, then in the libraries and the standard library
+    fn find(&self, name: &str) -> Option<Found> {
+        for (url, text, _) in self.sources() {
+            let document = match Document::parse(text) {
+                Ok(document) => document,
+                Err(_) => continue,
+            };
+            if let Some(outline) = document.outlines.into_iter().find(|o| o.module.name == name) {
+                let location = |span| Location::new(url.clone(), range(text, span));
+                return Some(Found {
+                    location: Some(location(outline.name)),
+                    wires: outline.wires.iter().map(|(idx, span)| (*idx, location(*span))).collect(),
+                    module: outline.module,
+                });
+            }
+        }
+        crate::parse(crate::STDLIB)
+            .into_iter()
+            .find(|m| m.name == name)
+            .map(|module| Found { module, location: None, wires: Vec::new() })
+    }
+
+    /// parse errors, and link errors of the modules in the document that are not instanced in it
+    pub fn diagnostics(&self, url: &Url) -> Vec<Diagnostic> {
+        let text = match self.text(url) {
+            Some(text) => text,
+            None => return Vec::new(),
+        };
+        let error = |range, message| Diagnostic {
+            range,
+            severity: Some(DiagnosticSeverity::ERROR),
+            source: Some(String::from("rva")),
+            message,
+            ..Diagnostic::default()
+        };
+
+        let document = match Document::parse(text) {
+            Ok(document) => document,
+            Err((line, message)) => {
+                let line = line as u32 - 1;
+                let end = text.lines().nth(line as usize).map(|l| l.encode_utf16().count()).unwrap_or(0);
+                return vec![error(Range::new(Position::new(line, 0), Position::new(line, end as u32)), message)];
+            },
+        };
+
+        // sources that do not parse are left out
+        let mut libraries = Vec::new();
+        let mut modules = Vec::new();
+        for (_, text, library) in self.sources() {
+            if let Ok(declarations) = crate::parse_declarations(text) {
+                let parsed = declarations.into_iter().filter_map(|d| match d {
+                    Declaration::Module(m) => Some(m),
+                    Declaration::Test(_) => None,
+                });
+                if library { libraries.extend(parsed) } else { modules.extend(parsed) }
+            }
+        }
+
+        let instanced: HashSet<&str> = document.outlines
+            .iter()
+            .flat_map(|o| o.module.instances.iter().map(|i| i.module.as_str()))
+            .collect();
+        document.outlines
+            .iter()
+            .filter(|o| !o.module.primitive && !instanced.contains(o.module.name.as_str()))
+            .filter_map(|o| {
+                let e = crate::build_with_libraries(libraries.clone(), modules.clone(), &o.module.name).err()?;
+                let message = crate::Error::Link { kind: e.kind, message: e.description }.to_string();
+                Some(error(range(text, o.name), message))
+            })
+            .collect()
+    }
+
+    /// the declaration of the module that is instanced, or of the port in a port list of an instance
+    pub fn definition(&self, url: &Url, position: Position) -> Option<Location> {
+        let text = self.text(url)?;
+        let document = Document::parse(text).ok()?;
+        let idx = document.word_at(offset(text, position))?;
+        let word = document.tokens[idx].text;
+
+        if let Some((module, ..)) = document.binding_list(idx).filter(|_| document.is_port_position(idx)) {
+            let found = self.find(module)?;
+            let local = found.module.locals.iter().position(|w| w.name == word)?;
+            return found.wires.into_iter().find(|(idx, _)| *idx == local).map(|(_, location)| location);
+        }
+        self.find(word)?.location
+    }
+
+    /// the ports of modules and the width of wires
+    pub fn hover(&self, url: &Url, position: Position) -> Option<Hover> {
+        let text = self.text(url)?;
+        let document = Document::parse(text).ok()?;
+        let at = offset(text, position);
+        let idx = document.word_at(at)?;
+        let word = document.tokens[idx].text;
+
+        if let Some((module, ..)) = document.binding_list(idx).filter(|_| document.is_port_position(idx)) {
+            let found = self.find(module)?;
+            let wire = found.module.locals.iter().find(|w| w.name == word && w.kind.is_port())?;
+            return Some(hover(format!("{} (port of {})", describe(wire), module)));
+        }
+        if let Some(wire) = document.outline_at(at).and_then(|o| o.module.locals.iter().find(|w| w.name == word)) {
+            return Some(hover(describe(wire)));
+        }
+        self.find(word).map(|found| hover(Header(&found.module).to_string()))
+    }
+
+    /// the ports that are not connected yet, inside of the port lists of an instance
+    pub fn completion(&self, url: &Url, position: Position) -> Vec<CompletionItem> {
+        let text = match self.text(url) {
+            Some(text) => text,
+            None => return Vec::new(),
+        };
+        // the document is usually incomplete while typing, so only the tokens are used
+        let document = Document {
+            tokens: lex(text).into_iter().filter(|t| t.kind != Kind::Comment).collect(),
+            outlines: Vec::new(),
+        };
+        let at = offset(text, position);
+        // the word that is being typed is completed as well
+        let idx = document.tokens.iter().position(|t| t.offset >= at || (t.kind == Kind::Word && at <= t.offset + t.text.len()))
+            .unwrap_or(document.tokens.len());
+        if idx == 0 || !(document.tokens[idx - 1].is("(") || document.tokens[idx - 1].is(",")) {
+            return Vec::new();
+        }
+        let (module, outputs, open) = match document.binding_list(idx) {
+            Some(list) => list,
+            None => return Vec::new(),
+        };
+        let found = match self.find(module) {
+            Some(found) => found,
+            None => return Vec::new(),
+        };
+
+        // ports that are already connected in the list
+        let connected: HashSet<&str> = document.tokens[open..idx]
+            .windows(2)
+            .filter(|w| w[1].is("="))
+            .map(|w| w[0].text)
+            .collect();
+        found.module.locals
+            .iter()
+            .filter(|w| match w.kind {
+                WireKind::Input | WireKind::InOut => !outputs,
+                WireKind::Output => outputs,
+                _ => false,
+            })
+            .filter(|w| !connected.contains(w.name.as_str()))
+            .map(|w| CompletionItem {
+                label: w.name.clone(),
+                kind: Some(CompletionItemKind::FIELD),
+                detail: Some(describe(w)),
+                ..CompletionItem::default()
+            })
+            .collect()
+    }
+
+    /// the modules of the document with their ports and wires
+    #[allow(deprecated)]
+    pub fn symbols(&self, url: &Url) -> Vec<DocumentSymbol> {
+        let text = match self.text(url) {
+            Some(text) => text,
+            None => return Vec::new(),
+        };
+        let document = match Document::parse(text) {
+            Ok(document) => document,
+            Err(_) => return Vec::new(),
+        };
+        let symbol = |name: String, detail: String, kind, span: Span, selection: Span, children| DocumentSymbol {
+            name,
+            detail: Some(detail),
+            kind,
+            tags: None,
+            deprecated: None,
+            range: range(text, span),
+            selection_range: range(text, selection),
+            children,
+        };
+        document.outlines
+            .iter()
+            .map(|o| {
+                let wires = o.wires
+                    .iter()
+                    .map(|(idx, span)| {
+                        let wire = &o.module.locals[*idx];
+                        let kind = if wire.kind.is_port() { SymbolKind::FIELD } else { SymbolKind::VARIABLE };
+                        symbol(wire.name.clone(), describe(wire), kind, *span, *span, None)
+                    })
+                    .collect();
+                symbol(o.module.name.clone(), Header(&o.module).to_string(), SymbolKind::MODULE, o.span, o.name, Some(wires))
+            })
+            .collect()
+    }
+}
+
+fn respond<P: DeserializeOwned, R: Serialize>(request: Request, handler: impl FnOnce(P) -> R) -> Response {
+    match serde_json::from_value(request.params) {
+        Ok(params) => Response::new_ok(request.id, handler(params)),
+        Err(e) => Response::new_err(request.id, ErrorCode::InvalidParams as i32, e.to_string()),
+    }
+}
+
+fn send(connection: &Connection, message: Message) -> Result<(), String> {
+    connection.sender.send(message).map_err(|e| e.to_string())
+}
+
+fn publish(connection: &Connection, url: Url, diagnostics: Vec<Diagnostic>) -> Result<(), String> {
+    let params = PublishDiagnosticsParams::new(url, diagnostics, None);
+    send(connection, Message::Notification(Notification::new(PublishDiagnostics::METHOD.to_string(), params)))
+}
+
+/// handles messages of the client until it shuts the server down
+pub fn serve(connection: &Connection) -> Result<(), String> {
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec![String::from("("), String::from(",")]),
+            ..CompletionOptions::default()
+        }),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        ..ServerCapabilities::default()
+    };
+    let capabilities = serde_json::to_value(capabilities).map_err(|e| e.to_string())?;
+    let params = connection.initialize(capabilities).map_err(|e| e.to_string())?;
+    let params: InitializeParams = serde_json::from_value(params).unwrap_or_default();
+    #[allow(deprecated)]
+    let root = params.root_uri.and_then(|url| url.to_file_path().ok());
+    let mut server = Server::new(root.as_deref());
+
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request).map_err(|e| e.to_string())? {
+                    return Ok(());
+                }
+                let response = match request.method.as_str() {
+                    GotoDefinition::METHOD => respond(request, |p: GotoDefinitionParams| {
+                        let p = p.text_document_position_params;
+                        server.definition(&p.text_document.uri, p.position).map(GotoDefinitionResponse::Scalar)
+                    }),
+                    HoverRequest::METHOD => respond(request, |p: HoverParams| {
+                        let p = p.text_document_position_params;
+                        server.hover(&p.text_document.uri, p.position)
+                    }),
+                    Completion::METHOD => respond(request, |p: CompletionParams| {
+                        let p = p.text_document_position;
+                        CompletionResponse::Array(server.completion(&p.text_document.uri, p.position))
+                    }),
+                    DocumentSymbolRequest::METHOD => respond(request, |p: DocumentSymbolParams| {
+                        DocumentSymbolResponse::Nested(server.symbols(&p.text_document.uri))
+                    }),
+                    method => {
+                        let message = format!("unsupported request '{}'", method);
+                        Response::new_err(request.id, ErrorCode::MethodNotFound as i32, message)
+                    },
+                };
+                send(connection, Message::Response(response))?;
+            },
+            Message::Notification(notification) => {
+                let params = notification.params;
+                match notification.method.as_str() {
+                    DidOpenTextDocument::METHOD => {
+                        if let Ok(p) = serde_json::from_value::<lsp_types::DidOpenTextDocumentParams>(params) {
+                            server.open(p.text_document.uri, p.text_document.text);
+                        }
+                    },
+                    DidChangeTextDocument::METHOD => {
+                        if let Ok(mut p) = serde_json::from_value::<lsp_types::DidChangeTextDocumentParams>(params) {
+                            // the whole text is sent on every change
+                            if let Some(change) = p.content_changes.pop() {
+                                server.open(p.text_document.uri, change.text);
+                            }
+                        }
+                    },
+                    DidSaveTextDocument::METHOD => {},
+                    DidCloseTextDocument::METHOD => {
+                        if let Ok(p) = serde_json::from_value::<lsp_types::DidCloseTextDocumentParams>(params) {
+                            server.close(&p.text_document.uri);
+                            // the client keeps the diagnostics of a closed document otherwise
+                            publish(connection, p.text_document.uri, Vec::new())?;
+                        }
+                    },
+                    _ => continue,
+                }
+                // a change may break or fix the other documents as well
+                for url in server.documents.keys() {
+                    publish(connection, url.clone(), server.diagnostics(url))?;
+                }
+            },
+            Message::Response(_) => {},
+        }
+    }
+    Ok(())
+}
+
+/// runs the language server on stdin and stdout
+pub fn run() -> Result<(), String> {
+    let (connection, io_threads) = Connection::stdio();
+    serve(&connection)?;
+    drop(connection);
+    io_threads.join().map_err(|e| e.to_string())
+}
@@ -0,0 +1,178 @@
+use crate::parsed::*;
+use crate::netgraph::*;
+
+use std::collections::*;
+
+/// outcome of a property in a single clock cycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// no check of the property finished in this cycle
+    Idle,
+    Pass,
+    Fail,
+}
+
+/// a property that did not hold in a clock cycle
+#[derive(Debug)]
+pub struct PropertyFailure {
+    /// path of the instance the property belongs to, empty for the root
+    pub path: String,
+    pub name: String,
+    pub cycle: usize,
+}
+
+impl std::fmt::Display for PropertyFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let path = if self.path.is_empty() { "<root>" } else { &self.path };
+        write!(f, "Property '{}' failed in '{}' in cycle {}", self.name, path, self.cycle)
+    }
+}
+
+enum Step {
+    Pass,
+    Fail,
+    Pending(Sequence<usize>),
+}
+
+/// advances a sequence by one clock cycle
+fn step(sequence: Sequence<usize>, sample: &HashMap<usize, bool>) -> Step {
+    let high = |addr: &usize| sample.get(addr).copied().unwrap_or(false);
+    match sequence {
+        Sequence::Holds(s) => if high(&s) { Step::Pass } else { Step::Fail },
+        Sequence::Next(seq) => Step::Pending(*seq),
+        Sequence::Within(n, s) => {
+            if high(&s) {
+                Step::Pass
+            } else if n == 0 {
+                Step::Fail
+            } else {
+                Step::Pending(Sequence::Within(n - 1, s))
+            }
+        },
+        Sequence::Until(s1, s2) => {
+            if high(&s2) {
+                Step::Pass
+            } else if high(&s1) {
+                Step::Pending(Sequence::Until(s1, s2))
+            } else {
+                Step::Fail
+            }
+        },
+    }
+}
+
+fn signals(sequence: &Sequence<usize>, out: &mut Vec<usize>) {
+    match sequence {
+        Sequence::Holds(s) | Sequence::Within(_, s) => out.push(*s),
+        Sequence::Next(seq) => signals(seq, out),
+        Sequence::Until(s1, s2) => out.extend([*s1, *s2]),
+    }
+}
+
+/// checks a property on every rising edge of its clock
+#[derive(Debug)]
+pub struct Monitor {
+    /// path of the instance the property belongs to, empty for the root
+    pub path: String,
+    pub property: Property<usize>,
+    signals: Vec<usize>,
+    /// checks that started in earlier cycles and are not decided yet
+    pending: Vec<Sequence<usize>>,
+    /// values of the signals in the last stable state
+    sample: HashMap<usize, bool>,
+    /// verdict for every clock cycle
+    pub history: Vec<Verdict>,
+}
+
+impl Monitor {
+    pub fn new(path: String, property: Property<usize>) -> Self {
+        let mut signals = vec![property.clock];
+        signals.extend(property.antecedent);
+        self::signals(&property.consequent, &mut signals);
+        signals.sort_unstable();
+        signals.dedup();
+
+        Monitor {
+            path,
+            property,
+            signals,
+            pending: Vec::new(),
+            sample: HashMap::new(),
+            history: Vec::new(),
+        }
+    }
+
+    /// Takes the values of a stable state. On a rising clock edge the pending checks are
+    /// advanced with the values sampled before the edge and the verdict of the cycle is returned.
+    pub fn sample(&mut self, wires: &[bool]) -> Option<Verdict> {
+        let clock = self.property.clock;
+        let rising = !self.sample.get(&clock).copied().unwrap_or(wires[clock]) && wires[clock];
+
+        let verdict = if rising {
+            Some(self.advance())
+        } else {
+            None
+        };
+
+        for &addr in self.signals.iter() {
+            self.sample.insert(addr, wires[addr]);
+        }
+        verdict
+    }
+
+    fn advance(&mut self) -> Verdict {
+        let mut checks = std::mem::take(&mut self.pending);
+        let triggered = match self.property.antecedent {
+            Some(addr) => self.sample[&addr],
+            None => true,
+        };
+        if triggered {
+            checks.push(self.property.consequent.clone());
+        }
+
+        let mut verdict = Verdict::Idle;
+        for check in checks.into_iter() {
+            match step(check, &self.sample) {
+                Step::Pass => if verdict == Verdict::Idle { verdict = Verdict::Pass },
+                Step::Fail => verdict = Verdict::Fail,
+                Step::Pending(seq) => self.pending.push(seq),
+            }
+        }
+        self.history.push(verdict);
+        verdict
+    }
+
+    /// the failure for the last cycle, if the property did not hold in it
+    pub fn failure(&self) -> Option<PropertyFailure> {
+        match self.history.last() {
+            Some(Verdict::Fail) => Some(PropertyFailure {
+                path: self.path.clone(),
+                name: self.property.name.clone(),
+                cycle: self.history.len() - 1,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// creates monitors for the properties of the module and all of its instances
+pub fn monitors(graph: &GraphModule) -> Vec<Monitor> {
+    let mut monitors = Vec::new();
+    collect_monitors(graph, String::new(), &mut monitors);
+    monitors
+}
+
+fn collect_monitors(graph: &GraphModule, path: String, monitors: &mut Vec<Monitor>) {
+    for property in graph.properties.iter() {
+        monitors.push(Monitor::new(path.clone(), property.clone()));
+    }
+
+    for inst in graph.instances.iter() {
+        let inst_path = if path.is_empty() {
+            inst.name.clone()
+        } else {
+            format!("{}.{}", path, inst.name)
+        };
+        collect_monitors(inst, inst_path, monitors);
+    }
+}
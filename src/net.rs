@@ -1,16 +1,52 @@
 use std::collections::*;
+use crate::monitor::*;
 
 #[derive(Debug)]
 pub struct Net {
     wires: Vec<bool>,
-    gates: Vec<NorGate>,
+    gates: Vec<Gate>,
+    /// wires with an initial value
+    initialized: HashSet<usize>,
 }
 
 #[derive(Debug)]
-pub struct NorGate {
-    in1: usize,
-    in2: usize,
-    out: usize,
+pub enum Gate {
+    Nor {
+        in1: usize,
+        in2: usize,
+        out: usize,
+    },
+    /// drives `out` with `input` while `enable` is high, leaves it floating otherwise
+    TriState {
+        input: usize,
+        enable: usize,
+        out: usize,
+    },
+}
+
+/// resolved state of a wire that is driven by tri-state gates
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum BusState {
+    /// no driver is active
+    Floating,
+    /// exactly one driver is active
+    Driven,
+    /// more than one driver is active
+    Contention,
+}
+
+/// toggles of a single wire while settling after which the simulation counts as oscillating
+pub const OSCILLATION_THRESHOLD: usize = 1000;
+/// number of recent toggles that are kept for every oscillating wire
+const HISTORY_LENGTH: usize = 8;
+
+/// a wire that toggled at least almost as often as the threshold since the simulation was last stable
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Toggles {
+    pub wire: usize,
+    pub count: usize,
+    /// gate update and new value of the latest toggles, oldest first
+    pub history: Vec<(usize, bool)>,
 }
 
 #[derive(Debug)]
@@ -22,6 +58,23 @@ pub struct Simulation {
     dirty: Vec<bool>,
     /// queue of dirty gates that need processing
     process_queue: VecDeque<usize>,
+    /// tri-state gates driving a wire, for all wires driven by tri-state gates
+    bus_drivers: HashMap<usize, Vec<usize>>,
+    /// resolved state of the wires driven by tri-state gates
+    bus_states: HashMap<usize, BusState>,
+    /// temporal properties that are checked on clock edges
+    monitors: Vec<Monitor>,
+    /// gate updates since the simulation was last stable or a wire was set
+    steps: usize,
+    /// changes of every wire since the simulation was last stable or a wire was set
+    toggles: Vec<usize>,
+    /// wires with a non-zero toggle count
+    toggled: Vec<usize>,
+    /// latest toggles of the wires that come close to the threshold
+    histories: HashMap<usize, VecDeque<(usize, bool)>>,
+    oscillation_threshold: usize,
+    /// a wire reached the threshold
+    oscillating: bool,
 }
 
 impl Net {
@@ -38,17 +91,57 @@ impl Net {
         assert!(in2 < self.wires.len());
         assert!(out < self.wires.len());
 
-        self.gates.push(NorGate { in1, in2, out });
+        self.gates.push(Gate::Nor { in1, in2, out });
+    }
+
+    /// creates a new tri-state gate with specified I/O
+    pub fn create_tristate(&mut self, input: usize, enable: usize, out: usize) {
+        assert!(input < self.wires.len());
+        assert!(enable < self.wires.len());
+        assert!(out < self.wires.len());
+
+        self.gates.push(Gate::TriState { input, enable, out });
+    }
+
+    /// returns a wire that is driven by a nor gate and any other gate, if there is one
+    pub fn conflicting_driver(&self) -> Option<usize> {
+        let mut drivers = vec![0usize; self.wires.len()];
+        let mut nor_driven = vec![false; self.wires.len()];
+        for gate in self.gates.iter() {
+            match *gate {
+                Gate::Nor { out, .. } => {
+                    drivers[out] += 1;
+                    nor_driven[out] = true;
+                },
+                Gate::TriState { out, .. } => drivers[out] += 1,
+            }
+        }
+        (0..self.wires.len()).find(|&w| nor_driven[w] && drivers[w] > 1)
     }
 
     pub fn set_value(&mut self, idx: usize, val: bool) {
         self.wires[idx] = val;
     }
 
+    pub fn wire_count(&self) -> usize {
+        self.wires.len()
+    }
+
+    pub fn gates(&self) -> &[Gate] {
+        &self.gates
+    }
+
+    /// sets the value a wire holds before the simulation starts
+    pub fn init_value(&mut self, idx: usize, val: bool) {
+        self.wires[idx] = val;
+        self.initialized.insert(idx);
+    }
+
     pub fn new() -> Self {
         Self {
             wires: Vec::new(),
             gates: Vec::new(),
+            initialized: HashSet::new(),
         }
     }
 }
@@ -59,25 +152,84 @@ impl Simulation {
         let mut process_queue = VecDeque::with_capacity(net.gates.len());
 
         let mut dependencies = vec![Vec::new(); net.wires.len()];
+        let mut bus_drivers = HashMap::new();
+        let mut initialized_gates = Vec::new();
         for (idx, gate) in net.gates.iter().enumerate() {
-            dependencies[gate.in1].push(idx);
-            dependencies[gate.in2].push(idx);
+            let out = match *gate {
+                Gate::Nor { in1, in2, out } => {
+                    dependencies[in1].push(idx);
+                    dependencies[in2].push(idx);
+                    out
+                },
+                Gate::TriState { input, enable, out } => {
+                    dependencies[input].push(idx);
+                    dependencies[enable].push(idx);
+                    bus_drivers.entry(out).or_insert_with(Vec::new).push(idx);
+                    out
+                },
+            };
 
-            process_queue.push_back(idx);
+            if net.initialized.contains(&out) {
+                initialized_gates.push(idx);
+            } else {
+                process_queue.push_back(idx);
+            }
         }
 
+        // gates driving wires with an initial value are processed last,
+        // so feedback loops like latches settle around the initial value
+        process_queue.extend(initialized_gates);
+
         for dep in dependencies.iter_mut() {
             dep.dedup();
         }
 
         Self {
+            toggles: vec![0; net.wires.len()],
             net,
             dependencies,
             dirty,
             process_queue,
+            bus_drivers,
+            bus_states: HashMap::new(),
+            monitors: Vec::new(),
+            steps: 0,
+            toggled: Vec::new(),
+            histories: HashMap::new(),
+            oscillation_threshold: OSCILLATION_THRESHOLD,
+            oscillating: false,
         }
     }
 
+    /// counts the change of the wire, only the last toggles of wires close to the threshold are recorded
+    #[inline]
+    fn record_toggle(&mut self, wire: usize, value: bool) {
+        let count = &mut self.toggles[wire];
+        if *count == 0 {
+            self.toggled.push(wire);
+        }
+        *count += 1;
+        if *count + HISTORY_LENGTH > self.oscillation_threshold {
+            let history = self.histories.entry(wire).or_default();
+            if history.len() == HISTORY_LENGTH {
+                history.pop_front();
+            }
+            history.push_back((self.steps, value));
+        }
+        if *count >= self.oscillation_threshold {
+            self.oscillating = true;
+        }
+    }
+
+    fn reset_toggles(&mut self) {
+        for wire in self.toggled.drain(..) {
+            self.toggles[wire] = 0;
+        }
+        self.histories.clear();
+        self.steps = 0;
+        self.oscillating = false;
+    }
+
     #[inline]
     fn enqueue_dependencies(&mut self, wire: usize) {
         for &gate in self.dependencies[wire].iter() {
@@ -97,22 +249,50 @@ impl Simulation {
         front
     }
 
+    /// resolves the value of a wire from all tri-state gates driving it,
+    /// a floating wire reads as low
+    fn resolve_bus(&mut self, wire: usize) -> bool {
+        let mut active = 0;
+        let mut value = false;
+        for &gate in self.bus_drivers[&wire].iter() {
+            if let Gate::TriState { input, enable, .. } = self.net.gates[gate] {
+                if self.net.wires[enable] {
+                    active += 1;
+                    value |= self.net.wires[input];
+                }
+            }
+        }
+
+        let state = match active {
+            0 => BusState::Floating,
+            1 => BusState::Driven,
+            _ => BusState::Contention,
+        };
+        self.bus_states.insert(wire, state);
+        value
+    }
+
     #[inline]
     pub fn update(&mut self) {
         if let Some(gate) = self.dequeue() {
-            let gate =  &self.net.gates[gate];
-
-            let in1 = self.net.wires[gate.in1];
-            let in2 = self.net.wires[gate.in2];
-            let orig = self.net.wires[gate.out];
-
-            let out = !(in1 || in2);
+            let (wire, out) = match self.net.gates[gate] {
+                Gate::Nor { in1, in2, out } => {
+                    let in1 = self.net.wires[in1];
+                    let in2 = self.net.wires[in2];
+                    (out, !(in1 || in2))
+                },
+                Gate::TriState { out, .. } => (out, self.resolve_bus(out)),
+            };
 
-            if orig != out {
-                self.net.wires[gate.out] = out;
-                let wire = gate.out;
+            self.steps += 1;
+            if self.net.wires[wire] != out {
+                self.net.wires[wire] = out;
+                self.record_toggle(wire, out);
                 self.enqueue_dependencies(wire);
             }
+            if self.process_queue.is_empty() {
+                self.reset_toggles();
+            }
         }
     }
 
@@ -121,8 +301,50 @@ impl Simulation {
         self.process_queue.is_empty()
     }
 
+    /// whether a wire toggled as often as the threshold since the simulation was last stable or a wire was set
+    pub fn is_oscillating(&self) -> bool {
+        self.oscillating
+    }
+
+    /// the wires that keep toggling, ordered by address, empty unless the simulation is oscillating
+    pub fn oscillations(&self) -> Vec<Toggles> {
+        if !self.is_oscillating() {
+            return Vec::new();
+        }
+        let mut wires: Vec<Toggles> = self.histories
+            .iter()
+            .map(|(&wire, history)| Toggles { wire, count: self.toggles[wire], history: history.iter().copied().collect() })
+            .collect();
+        wires.sort_unstable_by_key(|t| t.wire);
+        wires
+    }
+
+    pub fn set_oscillation_threshold(&mut self, threshold: usize) {
+        self.oscillation_threshold = threshold.max(1);
+    }
+
+    /// updates gates until the simulation is stable, oscillating, or `limit` gates have been updated,
+    /// returns whether it is stable
+    pub fn settle(&mut self, limit: usize) -> bool {
+        for _ in 0..limit {
+            if self.is_stable() || self.is_oscillating() {
+                break;
+            }
+            self.update();
+        }
+        self.is_stable()
+    }
+
+    pub fn net(&self) -> &Net {
+        &self.net
+    }
+
     #[inline]
     pub fn set_value(&mut self, addr: usize, value: bool) {
+        // a new stimulus starts counting toggles again
+        if !self.toggled.is_empty() {
+            self.reset_toggles();
+        }
         self.net.wires[addr] = value;
         self.enqueue_dependencies(addr);
     }
@@ -132,8 +354,49 @@ impl Simulation {
         self.net.wires[addr]
     }
 
+    /// state of a wire that is driven by tri-state gates
+    #[inline]
+    pub fn bus_state(&self, addr: usize) -> Option<BusState> {
+        self.bus_states.get(&addr).copied()
+    }
+
+    /// wires that are currently driven by more than one tri-state gate
+    pub fn contentions(&self) -> Vec<usize> {
+        let mut wires: Vec<_> = self.bus_states
+            .iter()
+            .filter(|(_, &state)| state == BusState::Contention)
+            .map(|(&wire, _)| wire)
+            .collect();
+        wires.sort_unstable();
+        wires
+    }
+
+    pub fn add_monitors(&mut self, monitors: Vec<Monitor>) {
+        self.monitors.extend(monitors);
+    }
+
+    pub fn monitors(&self) -> &[Monitor] {
+        &self.monitors
+    }
+
+    /// passes the current state to the property monitors, which should only happen when it is stable,
+    /// and returns the properties that failed on a clock edge
+    pub fn sample_monitors(&mut self) -> Vec<PropertyFailure> {
+        let mut failures = Vec::new();
+        for monitor in self.monitors.iter_mut() {
+            if monitor.sample(&self.net.wires).is_some() {
+                failures.extend(monitor.failure());
+            }
+        }
+        failures
+    }
+
     pub fn print_stats(&self) {
-        println!("Wires: {}, Nor gates: {}", self.net.wires.len(), self.net.gates.len());
+        let tristates = self.net.gates.iter().filter(|g| matches!(g, Gate::TriState { .. })).count();
+        println!(
+            "Wires: {}, Nor gates: {}, Tri-state gates: {}", 
+            self.net.wires.len(), self.net.gates.len() - tristates, tristates
+        );
     }
 }
 
@@ -141,7 +404,11 @@ impl crate::netgraph::WireDisplayer for Simulation {
     fn display_wire(&self, wire: &[usize]) -> String {
         let mut string = String::from("0b");
         for &i in wire.iter().rev() {
-            string += &format!("{}", self.net.wires[i] as u8);
+            match self.bus_state(i) {
+                Some(BusState::Floating) => string.push('z'),
+                Some(BusState::Contention) => string.push('x'),
+                _ => string += &format!("{}", self.net.wires[i] as u8),
+            }
         }
         string
     }
@@ -1,14 +1,24 @@
+use std::collections::HashMap;
+
+use crate::parsed::{Assertion, Property, WireKind};
+
 #[derive(Debug)]
 pub struct GraphModule {
     pub module_name: String,
     pub name: String,
+    /// the module is declared with `@latch`
+    pub latch: bool,
     pub locals: Vec<GraphWire>,
     pub instances: Vec<GraphModule>,
+    pub assertions: Vec<Assertion>,
+    /// properties with every signal resolved to a net address
+    pub properties: Vec<Property<usize>>,
 }
 
 #[derive(Debug)]
 pub struct GraphWire {
     pub name: String,
+    pub kind: WireKind,
     pub values: Vec<usize>,
 }
 
@@ -65,7 +75,7 @@ impl GraphModule {
             Ok(self.display(head, wd))
         } else {
             if let Some(i) = self.instances.iter().position(|i| i.name == path[0]) {
-                if head.len() > 0 {
+                if !head.is_empty() {
                     head.push('.');
                 }
                 head += &path[0];
@@ -92,6 +102,39 @@ impl GraphModule {
         }
     }
 
+    /// finds the hierarchical name of a bit in the net, e.g. 'reg.ff0.q[0]'
+    pub fn wire_path(&self, addr: usize) -> Option<String> {
+        for wire in self.locals.iter() {
+            if let Some(bit) = wire.values.iter().position(|&a| a == addr) {
+                return Some(format!("{}[{}]", wire.name, bit));
+            }
+        }
+        for inst in self.instances.iter() {
+            if let Some(path) = inst.wire_path(addr) {
+                return Some(format!("{}.{}", inst.name, path));
+            }
+        }
+        None
+    }
+
+    /// hierarchical names of all bits in the net, bits shared by several wires get the outermost name
+    pub fn wire_names(&self) -> HashMap<usize, String> {
+        let mut names = HashMap::new();
+        self.collect_names(String::new(), &mut names);
+        names
+    }
+
+    fn collect_names(&self, prefix: String, names: &mut HashMap<usize, String>) {
+        for wire in self.locals.iter() {
+            for (bit, &addr) in wire.values.iter().enumerate() {
+                names.entry(addr).or_insert_with(|| format!("{}{}[{}]", prefix, wire.name, bit));
+            }
+        }
+        for inst in self.instances.iter() {
+            inst.collect_names(format!("{}{}.", prefix, inst.name), names);
+        }
+    }
+
     pub fn wire_width(&self, path: &[String]) -> Result<u64, Error> {
         if path.is_empty() {
             Err(Error::InvalidPath(String::from("This path refers to a module")))
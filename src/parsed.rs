@@ -6,9 +6,25 @@ pub enum WireKind {
     Input,
     /// Accessible to the local scope and as an output in an instance
     Output,
+    /// Only accessible to the local scope, may be driven by multiple tri-state gates
+    Bus,
+    /// Accessible to the local scope and as a bus in an instance
+    InOut,
 }
 
-#[derive(PartialEq, Eq, Debug)]
+impl WireKind {
+    /// whether the wire is part of the I/O of a module
+    pub fn is_port(self) -> bool {
+        matches!(self, WireKind::Input | WireKind::Output | WireKind::InOut)
+    }
+
+    /// whether the wire may be driven by multiple tri-state gates
+    pub fn is_bus(self) -> bool {
+        matches!(self, WireKind::Bus | WireKind::InOut)
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub struct Wire {
     pub name: String,
 
@@ -16,18 +32,24 @@ pub struct Wire {
     pub width: usize,
 
     pub kind: WireKind,
+
+    /// constant an input is tied to if it is not assigned in an instantiation
+    pub default: Option<Vec<bool>>,
+
+    /// value the wire holds before the first simulation step
+    pub init: Option<Vec<bool>>,
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub struct Connection {
-    /// Local wires
+    /// Local wires, empty if the output is discarded
     pub local: WireBus,
 
     /// Instanced wire name
     pub module: String,
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub struct Instance {
     pub module: String,
     pub name: String,
@@ -35,7 +57,7 @@ pub struct Instance {
     pub outputs: Vec<Connection>,
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub struct Module {
     /// Name of the module
     pub name: String,
@@ -45,15 +67,27 @@ pub struct Module {
 
     /// Local Sub-Module instances
     pub instances: Vec<Instance>,
+
+    /// Declared with `primitive`, implemented by the native gate with the same name
+    pub primitive: bool,
+
+    /// Declared with `@latch`, feedback loops within the module are intended
+    pub latch: bool,
+
+    /// Checks that are evaluated whenever the simulation is stable
+    pub assertions: Vec<Assertion>,
+
+    /// Checks across clock cycles, every signal is a single bit
+    pub properties: Vec<Property<WireBus>>,
 }
 
-#[derive(PartialEq, Eq, Debug, Clone)]
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
 pub enum WireRange {
     Ranged {from: usize, to: usize},
     Total,
 }
 
-#[derive(PartialEq, Eq, Debug, Clone)]
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
 pub enum WirePart {
     Local{name: String, range: WireRange},
     Constant(Vec<bool>),
@@ -65,6 +99,65 @@ pub struct WireAssignment {
     pub operation: Operation,
 }
 
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Assertion {
+    /// holds if all bits of the condition are high
+    pub condition: Operation,
+    /// the assertion is only checked while any bit of this is high
+    pub enable: Option<Operation>,
+    pub message: Option<String>,
+    /// source text of the assertion
+    pub source: String,
+}
+
+/// a sequence of signals over clock cycles, a signal is high if all of its bits are high
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Sequence<T> {
+    /// the signal is high in the current cycle
+    Holds(T),
+    /// the sequence holds starting with the next cycle
+    Next(Box<Sequence<T>>),
+    /// the signal is high in the current cycle or one of the following cycles
+    Within(usize, T),
+    /// the first signal stays high until the second one is high
+    Until(T, T),
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Property<T> {
+    pub name: String,
+    /// the property is sampled on the rising edges of the clock
+    pub clock: T,
+    /// the consequent is only checked starting in cycles where the antecedent is high
+    pub antecedent: Option<T>,
+    pub consequent: Sequence<T>,
+}
+
+impl<T> Sequence<T> {
+    pub fn try_map<U, E, F: FnMut(T) -> Result<U, E>>(self, f: &mut F) -> Result<Sequence<U>, E> {
+        Ok(match self {
+            Sequence::Holds(s) => Sequence::Holds(f(s)?),
+            Sequence::Next(seq) => Sequence::Next(Box::new(seq.try_map(f)?)),
+            Sequence::Within(n, s) => Sequence::Within(n, f(s)?),
+            Sequence::Until(s1, s2) => Sequence::Until(f(s1)?, f(s2)?),
+        })
+    }
+}
+
+impl<T> Property<T> {
+    pub fn try_map<U, E, F: FnMut(T) -> Result<U, E>>(self, f: &mut F) -> Result<Property<U>, E> {
+        Ok(Property {
+            name: self.name,
+            clock: f(self.clock)?,
+            antecedent: match self.antecedent {
+                Some(a) => Some(f(a)?),
+                None => None,
+            },
+            consequent: self.consequent.try_map(f)?,
+        })
+    }
+}
+
 type Op = Box<Operation>;
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub enum Operation {
@@ -72,6 +165,7 @@ pub enum Operation {
     And(Op, Op),
     Or(Op, Op),
     Xor(Op, Op),
+    Eq(Op, Op),
     AndReduce(Op),
     OrReduce(Op),
     XorReduce(Op),
@@ -96,7 +190,7 @@ impl WirePart {
     pub fn constant(constant: Vec<bool>) -> Self {
         Self::Constant(constant)
     }
-    pub fn width(&self, module: &Module) -> Result<usize, ()> {
+    pub(crate) fn width(&self, module: &Module) -> Result<usize, ()> {
         match self {
             Self::Constant(c) => Ok(c.len()),
             Self::Local{name, range} => {
@@ -117,7 +211,26 @@ impl WirePart {
 }
 
 impl Operation {
-    pub fn width(&self, module: &Module) -> Result<usize, ()> {
+    /// names of the local wires the operation uses, in the order they appear
+    pub(crate) fn wire_names(&self) -> Vec<&str> {
+        match self {
+            Self::Wire(bus) => bus
+                .iter()
+                .filter_map(|p| match p {
+                    WirePart::Local { name, .. } => Some(name.as_str()),
+                    WirePart::Constant(_) => None,
+                })
+                .collect(),
+            Self::And(op1, op2) | Self::Or(op1, op2) | Self::Xor(op1, op2) | Self::Eq(op1, op2) => {
+                let mut names = op1.wire_names();
+                names.extend(op2.wire_names());
+                names
+            },
+            Self::Not(op) | Self::AndReduce(op) | Self::OrReduce(op) | Self::XorReduce(op) => op.wire_names(),
+        }
+    }
+
+    pub(crate) fn width(&self, module: &Module) -> Result<usize, ()> {
         match self {
             Self::Wire(bus) => bus.iter().map(|w| w.width(module)).sum(),
             Self::And(op1, op2) => op1.width(module).max(op2.width(module)),
@@ -129,3 +242,22 @@ impl Operation {
     }
 }
 
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum TestStep {
+    /// sets the wire at the path, without running the simulation
+    Set(Vec<String>, u64),
+    /// runs the simulation until it is stable
+    Settle,
+    /// fails the test if the wire at the path has a different value
+    Expect(Vec<String>, u64),
+    /// settles, then raises and lowers the clock at the path for a number of cycles, settling after every edge
+    Clock(Vec<String>, usize),
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct TestBench {
+    pub name: String,
+    /// module that is elaborated standalone for the test
+    pub module: String,
+    pub steps: Vec<TestStep>,
+}
@@ -31,14 +31,10 @@ fn binary_number(i: &str) -> IResult<&str, Vec<bool>> {
 
 fn hex_digit(i: &str) -> IResult<&str, Vec<bool>> {
     map (
-        take_while_m_n(1, 1, |c: char| c.is_digit(16)), 
+        take_while_m_n(1, 1, |c: char| c.is_ascii_hexdigit()), 
         |s| {
             let digit = u8::from_str_radix(s, 16).unwrap();
-            let mut bits = vec![false; 4];
-            for idx in 0..4 {
-                bits[idx] = (digit >> idx)&1 > 0;
-            }
-            bits
+            (0..4).map(|idx| (digit >> idx)&1 > 0).collect()
         }
     )(i)
 }
@@ -79,8 +75,8 @@ pub fn module_name(i: &str) -> IResult<&str, String> {
 
 pub fn number(i: &str) -> IResult<&str, usize> {
     map_res(
-        take_while1(|c: char| c.is_digit(10)),
-        |i| usize::from_str_radix(i, 10)
+        take_while1(|c: char| c.is_ascii_digit()),
+        |i: &str| i.parse::<usize>()
     )(i)
 }
 
@@ -112,12 +108,10 @@ fn wirepart(i: &str) -> IResult<&str, WirePart> {
                 tuple((field_name, range)),
                 |(id, range)| WirePart::ranged(id, range.0, range.1)
             ),
-            map(field_name,
-                |id| WirePart::total(id),
-            ),
+            map(field_name, WirePart::total),
             map(
                 wire_constant,
-                |c| WirePart::constant(c)
+                WirePart::constant
             ),
     ))(i)
 }
@@ -130,9 +124,11 @@ fn comment(i: &str) -> IResult<&str, &str> {
 }
 
 pub fn whitespace(i: &str) -> IResult<&str, &str> {
-    terminated(
-        take_while(|c: char| c.is_ascii_whitespace()),
-        opt(comment),
+    recognize(
+        many0(alt((
+            take_while1(|c: char| c.is_ascii_whitespace()),
+            comment,
+        )))
     )(i)
 }
 
@@ -149,7 +145,7 @@ pub fn list<'a, T, F: Copy + Fn(&str) -> IResult<&str, T>>
                 ),
                 opt(parser),
         )),
-        |(list, last)| list.into_iter().chain(last.into_iter()).collect()
+        |(list, last)| list.into_iter().chain(last).collect()
     )
 }
 
@@ -205,6 +201,8 @@ fn wire(i: &str) -> IResult<&str, Wire> {
                     name,
                     width,
                     kind: WireKind::Private,
+                    default: None,
+                    init: None,
                 }
             ),
             map(
@@ -212,25 +210,126 @@ fn wire(i: &str) -> IResult<&str, Wire> {
                 |name| Wire { 
                     name, 
                     width: 1, 
-                    kind: WireKind::Private },
+                    kind: WireKind::Private,
+                    default: None,
+                    init: None },
             )
     ))(i)
 }
 
+fn initialized_wire(i: &str) -> IResult<&str, Wire> {
+    map(
+        tuple((
+                wire,
+                opt(preceded(
+                        tuple((whitespace, tag("init"), whitespace)),
+                        wire_constant,
+                )),
+        )),
+        |(w, init)| Wire { init, ..w }
+    )(i)
+}
+
 fn local_wire(i: &str) -> IResult<&str, Vec<Wire>> {
     map(
         tuple((
                 tag("wire"),
                 whitespace,
-                list(wire, ","),
+                list(initialized_wire, ","),
                 tag(";"),
         )),
         |(_, _, w, _)| w
     )(i)
 }
 
+/// a wire that is declared and assigned at once, the width is inferred from the operation if it is omitted
+struct WireDeclaration {
+    name: String,
+    width: Option<usize>,
+    operation: Operation,
+    /// length of the input that starts with the declaration, to find it in the source again
+    rest: usize,
+}
+
+fn wire_declaration(i: &str) -> IResult<&str, WireDeclaration> {
+    map(
+        tuple((
+                rest_len,
+                tag("wire"),
+                multispace1,
+                field_name,
+                opt(index),
+                whitespace,
+                tag("="),
+                whitespace,
+                operation,
+                tag(";"),
+        )),
+        |(rest, _, _, name, width, _, _, _, operation, _)| WireDeclaration { name, width, operation, rest }
+    )(i)
+}
+
+/// a register that takes the value of the operation on the rising edge of the clock
+struct RegisterDeclaration {
+    wire: Wire,
+    clock: String,
+    operation: Operation,
+}
+
+fn register_declaration(i: &str) -> IResult<&str, RegisterDeclaration> {
+    map(
+        tuple((
+                tag("reg"),
+                whitespace,
+                initialized_wire,
+                whitespace,
+                tag("="),
+                whitespace,
+                operation,
+                whitespace,
+                tag("@"),
+                whitespace,
+                field_name,
+                whitespace,
+                tag(";"),
+        )),
+        |(_, _, wire, _, _, _, operation, _, _, _, clock, _, _)| RegisterDeclaration { wire, clock, operation }
+    )(i)
+}
+
+fn local_bus(i: &str) -> IResult<&str, Vec<Wire>> {
+    map(
+        tuple((
+                tag("bus"),
+                multispace1,
+                list(wire, ","),
+                tag(";"),
+        )),
+        |(_, _, w, _)| w.into_iter().map(|w| Wire { kind: WireKind::Bus, ..w }).collect()
+    )(i)
+}
+
 fn input_wire(i: &str) -> IResult<&str, Wire> {
-    map(wire, |w| Wire { kind: WireKind::Input, ..w})(i)
+    alt((
+        map(
+            preceded(tuple((tag("inout"), multispace1)), wire),
+            |w| Wire { kind: WireKind::InOut, ..w }
+        ),
+        default_input_wire,
+    ))(i)
+}
+
+fn default_input_wire(i: &str) -> IResult<&str, Wire> {
+    map(
+        tuple((
+                wire,
+                opt(preceded(
+                        tuple((whitespace, tag("="), whitespace)),
+                        wire_constant,
+                )),
+        )),
+        |(w, default)| Wire { kind: WireKind::Input, default, ..w}
+    )(i)
 }
 
 fn output_wire(i: &str) -> IResult<&str, Wire> {
@@ -239,6 +338,19 @@ fn output_wire(i: &str) -> IResult<&str, Wire> {
 
 fn io_binding(i: &str) -> IResult<&str, Connection> {
     alt((
+            map(
+                tuple((
+                        field_name,
+                        whitespace,
+                        tag("="),
+                        whitespace,
+                        tag("_"),
+                )),
+                |(name, _, _, _, _)| Connection { 
+                    local: Vec::new(), 
+                    module: name 
+                }
+            ),
             map(
                 tuple((
                         field_name,
@@ -343,6 +455,7 @@ fn operation(i: &str) -> IResult<&str, Operation> {
             binary_operation("&", Operation::And),
             binary_operation("|", Operation::Or),
             binary_operation("^", Operation::Xor),
+            binary_operation("==", Operation::Eq),
             operation_literal,
     ))(i)
 }
@@ -362,11 +475,86 @@ fn wire_assignment(i: &str) -> IResult<&str, WireAssignment> {
     )(i)
 }
 
-fn module_header(i: &str) -> IResult<&str, (String, Vec<Wire>, Vec<Wire>)> {
+/// name, inputs and outputs of a module
+type ModuleHeader = (String, Vec<Wire>, Vec<Wire>);
+
+fn string_literal(i: &str) -> IResult<&str, String> {
+    map(
+        delimited(char('"'), take_until("\""), char('"')),
+        String::from
+    )(i)
+}
+
+fn assertion(i: &str) -> IResult<&str, Assertion> {
+    let (rest, (_, _, condition, enable, message, _, _)) = tuple((
+            tag("assert"),
+            whitespace,
+            operation,
+            opt(preceded(
+                    tuple((whitespace, tag("when"), whitespace)),
+                    operation,
+            )),
+            opt(preceded(whitespace, string_literal)),
+            whitespace,
+            tag(";"),
+    ))(i)?;
+    let source = i[..i.len() - rest.len()].to_string();
+    Ok((rest, Assertion { condition, enable, message, source }))
+}
+
+fn sequence(i: &str) -> IResult<&str, Sequence<Operation>> {
+    alt((
+            map(
+                preceded(tuple((tag("next"), multispace1)), sequence),
+                |s| Sequence::Next(Box::new(s))
+            ),
+            map(
+                tuple((tag("within"), multispace1, number, multispace1, operation)),
+                |(_, _, n, _, op)| Sequence::Within(n, op)
+            ),
+            map(
+                tuple((operation, whitespace, tag("until"), multispace1, operation)),
+                |(op1, _, _, _, op2)| Sequence::Until(op1, op2)
+            ),
+            map(operation, Sequence::Holds),
+    ))(i)
+}
+
+fn property(i: &str) -> IResult<&str, Property<Operation>> {
+    map(
+        tuple((
+                tag("property"),
+                multispace1,
+                field_name,
+                whitespace,
+                tag("@"),
+                whitespace,
+                wirepart,
+                whitespace,
+                tag(":"),
+                whitespace,
+                opt(terminated(
+                        operation,
+                        tuple((whitespace, tag("|->"), whitespace)),
+                )),
+                sequence,
+                whitespace,
+                tag(";"),
+        )),
+        |(_, _, name, _, _, _, clock, _, _, _, antecedent, consequent, _, _)| Property {
+            name,
+            clock: Operation::Wire(vec![clock]),
+            antecedent,
+            consequent,
+        }
+    )(i)
+}
+
+fn module_header<'a>(keyword: &'static str) -> impl Fn(&'a str) -> IResult<&'a str, ModuleHeader> {
     map(
         tuple((
                 whitespace,
-                tag("module"),
+                tag(keyword),
                 whitespace,
                 module_name,
                 whitespace,
@@ -386,19 +574,28 @@ fn module_header(i: &str) -> IResult<&str, (String, Vec<Wire>, Vec<Wire>)> {
                 whitespace
         )),
         |(_, _, _, name, _, inputs, _, _, _, outputs, _)| (name, inputs, outputs)
-    )(i)
+    )
 }
 
 enum BodyPart {
     LocalWire(Vec<Wire>),
+    WireDeclaration(WireDeclaration),
+    RegisterDeclaration(RegisterDeclaration),
     Instance(Instance),
     Assignment(WireAssignment),
+    Assertion(Assertion),
+    Property(Property<Operation>),
 }
 
 fn body_part (i: &str) -> IResult<&str, BodyPart> {
     alt((
             map(local_wire, BodyPart::LocalWire),
+            map(wire_declaration, BodyPart::WireDeclaration),
+            map(register_declaration, BodyPart::RegisterDeclaration),
+            map(local_bus, BodyPart::LocalWire),
             map(instance, BodyPart::Instance),
+            map(assertion, BodyPart::Assertion),
+            map(property, BodyPart::Property),
             map(wire_assignment, BodyPart::Assignment),
     ))(i)
 }
@@ -415,13 +612,31 @@ fn module_body(i: &str) -> IResult<&str, Vec<BodyPart>> {
     )(i)
 }
 
-fn module(i: &str) -> IResult<&str, Module> {
-    map_res::<_, _, _, _, (), _, _>(
-        tuple((module_header, whitespace, module_body)),
-        |((name, mut inputs, mut outputs), _,  body)| {
+/// a module as it is written, before its declarations and assignments are resolved
+struct ModuleParts {
+    module: Module,
+    declarations: Vec<WireDeclaration>,
+    assignments: Vec<WireAssignment>,
+    registers: Vec<(String, String, Operation)>,
+    properties: Vec<Property<Operation>>,
+}
+
+fn module_parts(i: &str) -> IResult<&str, ModuleParts> {
+    map(
+        tuple((
+                opt(preceded(whitespace, terminated(tag("@latch"), peek(multispace1)))),
+                module_header("module"),
+                whitespace,
+                module_body,
+        )),
+        |(latch, (name, mut inputs, mut outputs), _,  body)| {
             let mut locals = Vec::new();
             let mut instances = Vec::new();
             let mut assignments = Vec::new();
+            let mut declarations = Vec::new();
+            let mut registers = Vec::new();
+            let mut assertions = Vec::new();
+            let mut properties = Vec::new();
 
             locals.append(&mut inputs);
             locals.append(&mut outputs);
@@ -429,26 +644,243 @@ fn module(i: &str) -> IResult<&str, Module> {
             for line in body {
                 match line {
                     BodyPart::LocalWire(mut w) => locals.append(&mut w),
+                    BodyPart::WireDeclaration(d) => declarations.push(d),
+                    BodyPart::RegisterDeclaration(r) => {
+                        registers.push((r.wire.name.clone(), r.clock, r.operation));
+                        locals.push(r.wire);
+                    },
                     BodyPart::Instance(i) => instances.push(i),
                     BodyPart::Assignment(a) => assignments.push(a),
+                    BodyPart::Assertion(a) => assertions.push(a),
+                    BodyPart::Property(p) => properties.push(p),
                 }
             }
 
-            let mut module = Module { name, locals, instances };
+            let module = Module { 
+                name, locals, instances, primitive: false, latch: latch.is_some(), assertions, properties: Vec::new() 
+            };
+            ModuleParts { module, declarations, assignments, registers, properties }
+        }
+    )(i)
+}
+
+/// an invalid wire declaration
+struct DeclarationError {
+    /// length of the input that starts with the declaration
+    rest: usize,
+    message: String,
+}
+
+/// Declares the wires and assigns their operations to them. A declaration may use wires that
+/// are declared after it, so declarations are resolved once the widths of all wires they use are known.
+fn resolve_declarations(
+    module: &mut Module, mut pending: Vec<WireDeclaration>, assignments: &mut Vec<WireAssignment>,
+) -> Result<(), DeclarationError> {
+    while !pending.is_empty() {
+        let known = |name: &str| module.locals.iter().any(|w| w.name == name);
+        let unknown = |d: &WireDeclaration| {
+            d.operation.wire_names().into_iter().find(|n| !known(n)).map(String::from)
+        };
+        let declaration = match pending.iter().position(|d| unknown(d).is_none()) {
+            Some(idx) => pending.remove(idx),
+            None => {
+                // a wire that is not declared at all is reported before declarations that depend on each other
+                let undeclared = pending
+                    .iter()
+                    .map(|d| (d, unknown(d).unwrap_or_default()))
+                    .find(|(_, name)| !pending.iter().any(|p| p.name == *name));
+                let (declaration, message) = match undeclared {
+                    Some((d, name)) => (d, format!("wire '{}' uses the unknown wire '{}'", d.name, name)),
+                    None => {
+                        let d = &pending[0];
+                        (d, format!("the width of wire '{}' depends on itself through '{}'", d.name, unknown(d).unwrap_or_default()))
+                    },
+                };
+                return Err(DeclarationError { rest: declaration.rest, message });
+            },
+        };
+
+        let inferred = declaration.operation.width(module).map_err(|_| DeclarationError {
+            rest: declaration.rest,
+            message: format!("the width of wire '{}' can not be inferred", declaration.name),
+        })?;
+        if let Some(width) = declaration.width.filter(|&w| w != inferred) {
+            return Err(DeclarationError {
+                rest: declaration.rest,
+                message: format!(
+                    "wire '{}' is declared with {} bits, but its operation has {} bits",
+                    declaration.name, width, inferred
+                ),
+            });
+        }
+        module.locals.push(Wire {
+            name: declaration.name.clone(),
+            width: inferred,
+            kind: WireKind::Private,
+            default: None,
+            init: None,
+        });
+        assignments.push(WireAssignment {
+            bus: vec![WirePart::total(declaration.name)],
+            operation: declaration.operation,
+        });
+    }
+    Ok(())
+}
+
+fn module(i: &str) -> IResult<&str, Module> {
+    map_res::<_, _, _, _, (), _, _>(
+        module_parts,
+        |parts| {
+            let ModuleParts { mut module, declarations, mut assignments, registers, properties } = parts;
+            resolve_declarations(&mut module, declarations, &mut assignments).map_err(|_| ())?;
+
             let mut resolver = assignment::Resolver::new(&mut module);
             resolver.resolve_assignments(assignments)?;
+            for (name, clock, operation) in registers.into_iter() {
+                resolver.resolve_register(&name, &clock, operation)?;
+            }
+            let properties = properties
+                .into_iter()
+                .map(|p| resolver.resolve_property(p))
+                .collect::<Result<_, _>>()?;
+            module.properties = properties;
             Ok(module)
         }
     )(i)
 }
 
-pub fn modules(i: &str) -> IResult<&str, Vec<Module>> {
-    let (rest, modules) = many0(module)(i)?;
-    if rest.len() > 0 {
+/// The offset and message of the first invalid wire declaration of the module at the start of the input,
+/// if that is why the module does not parse.
+pub fn declaration_error(i: &str) -> Option<(usize, String)> {
+    let (_, mut parts) = module_parts(i).ok()?;
+    let error = resolve_declarations(&mut parts.module, parts.declarations, &mut parts.assignments).err()?;
+    Some((i.len() - error.rest, error.message))
+}
+
+fn primitive(i: &str) -> IResult<&str, Module> {
+    map(
+        tuple((module_header("primitive"), tag(";"), whitespace)),
+        |((name, mut inputs, mut outputs), _, _)| {
+            let mut locals = Vec::new();
+            locals.append(&mut inputs);
+            locals.append(&mut outputs);
+            Module { 
+                name, locals, instances: Vec::new(), primitive: true, latch: false,
+                assertions: Vec::new(), properties: Vec::new() 
+            }
+        }
+    )(i)
+}
+
+/// an unsigned integer in hexadecimal (0x), binary (0b) or decimal notation
+pub fn integer(i: &str) -> IResult<&str, u64> {
+    alt((
+            map_res(
+                preceded(tag("0x"), take_while1(|c: char| c.is_ascii_hexdigit())),
+                |s| u64::from_str_radix(s, 16)
+            ),
+            map_res(
+                preceded(tag("0b"), take_while1(|c: char| c == '0' || c == '1')),
+                |s| u64::from_str_radix(s, 2)
+            ),
+            map_res(
+                take_while1(|c: char| c.is_ascii_digit()),
+                |s: &str| s.parse::<u64>()
+            ),
+    ))(i)
+}
+
+pub fn wire_path(i: &str) -> IResult<&str, Vec<String>> {
+    separated_nonempty_list(tag("."), field_name)(i)
+}
+
+/// parses the whole input with the parser, fails if anything is left
+pub fn parse_complete<T>(i: &str, parser: fn(&str) -> IResult<&str, T>) -> Option<T> {
+    match parser(i) {
+        Ok(("", parsed)) => Some(parsed),
+        _ => None,
+    }
+}
+
+fn test_step(i: &str) -> IResult<&str, TestStep> {
+    terminated(
+        alt((
+                map(
+                    tuple((tag("set"), multispace1, wire_path, whitespace, tag("="), whitespace, integer)),
+                    |(_, _, path, _, _, _, value)| TestStep::Set(path, value)
+                ),
+                map(tag("settle"), |_| TestStep::Settle),
+                map(
+                    tuple((tag("expect"), multispace1, wire_path, whitespace, tag("=="), whitespace, integer)),
+                    |(_, _, path, _, _, _, value)| TestStep::Expect(path, value)
+                ),
+                map(
+                    tuple((tag("clock"), multispace1, wire_path, multispace1, number)),
+                    |(_, _, path, _, cycles)| TestStep::Clock(path, cycles)
+                ),
+        )),
+        tuple((whitespace, tag(";")))
+    )(i)
+}
+
+fn testbench(i: &str) -> IResult<&str, TestBench> {
+    map(
+        tuple((
+                whitespace,
+                tag("test"),
+                whitespace,
+                string_literal,
+                whitespace,
+                tag("for"),
+                multispace1,
+                module_name,
+                whitespace,
+                delimited(
+                    tag("{"),
+                    many0(delimited(whitespace, test_step, whitespace)),
+                    tuple((tag("}"), whitespace))
+                ),
+        )),
+        |(_, _, _, name, _, _, _, module, _, steps)| TestBench { name, module, steps }
+    )(i)
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum Declaration {
+    Module(Module),
+    Test(TestBench),
+}
+
+fn declaration(i: &str) -> IResult<&str, Declaration> {
+    alt((
+            map(module, Declaration::Module),
+            map(primitive, Declaration::Module),
+            map(testbench, Declaration::Test),
+    ))(i)
+}
+
+pub fn declarations(i: &str) -> IResult<&str, Vec<Declaration>> {
+    let (rest, declarations) = many0(declaration)(i)?;
+    if !rest.is_empty() {
         // should return an error, as there is an unparsed rest that is 
-        // apparently not a valid module
-        module(rest)?;
+        // apparently not a valid declaration
+        declaration(rest)?;
     }
-    Ok((rest, modules))
+    Ok((rest, declarations))
+}
+
+/// parses all declarations, but only keeps the modules
+pub fn modules(i: &str) -> IResult<&str, Vec<Module>> {
+    map(
+        declarations,
+        |declarations| declarations
+            .into_iter()
+            .filter_map(|d| match d {
+                Declaration::Module(m) => Some(m),
+                Declaration::Test(_) => None,
+            })
+            .collect()
+    )(i)
 }
 
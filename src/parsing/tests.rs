@@ -1,7 +1,5 @@
 use super::*;
 
-use crate::parsed::*;
-
 #[test]
 fn hex_digit_test() {
     assert_eq!(hex_digit("F"), Ok(("", vec![true; 4])));
@@ -14,7 +12,7 @@ fn hex_digit_test() {
 fn hex_number_test() {
     assert_eq!(hex_number("0x42"), Ok(("", vec![false, true, false, false, false, false, true, false])));
     match hex_number("0xC0FFEE") {
-        Err(e) => assert!(false, "Couldn't parse 0xC0FFEE"),
+        Err(_) => panic!("Couldn't parse 0xC0FFEE"),
         Ok((rest, num)) => {
             assert_eq!(rest, "");
             assert_eq!(num.len(), 24);
@@ -35,7 +33,7 @@ fn number_test() {
 
 #[test]
 fn range_test() {
-    assert_eq!(range("[5:1]"), Ok(("", ((5, 1)))));
+    assert_eq!(range("[5:1]"), Ok(("", (5, 1))));
 }
 
 #[test]
@@ -65,6 +63,7 @@ fn wirepart_test() {
 fn whitespace_test() {
     assert_eq!(whitespace("  \n\t ...\n"), Ok(("...\n", "  \n\t ")));
     assert_eq!(whitespace(" word "), Ok(("word ", " ")));
+    assert_eq!(whitespace("// one\n// two\n word"), Ok(("word", "// one\n// two\n ")));
 }
 
 #[test]
@@ -110,7 +109,9 @@ fn wire_test() {
         Ok(("", Wire { 
             name: "peter".to_string(), 
             width: 5, 
-            kind: WireKind::Private 
+            kind: WireKind::Private,
+            default: None,
+            init: None,
         }))
     );
     assert_eq!(
@@ -118,7 +119,9 @@ fn wire_test() {
         Ok((" ", Wire { 
             name: "hans".to_string(), 
             width: 1, 
-            kind: WireKind::Private 
+            kind: WireKind::Private,
+            default: None,
+            init: None,
         }))
     );
 }
@@ -129,11 +132,15 @@ fn local_wire_test() {
         name: "rudolf".to_string(),
         width: 1,
         kind: WireKind::Private,
+        default: None,
+        init: None,
     }])));
     assert_eq!(local_wire("wire stefan[278];"), Ok(("", vec![Wire {
         name: "stefan".to_string(),
         width: 278,
         kind: WireKind::Private,
+        default: None,
+        init: None,
     }])));
 }
 
@@ -252,7 +259,7 @@ fn wire_assignment_test() {
         wire_assignment("wire[5:10] = (!in1[0:5] | in2) & in3;"),
         Ok(("",
                 WireAssignment {
-                    wire: vec![WirePart::ranged("wire", 5, 10)],
+                    bus: vec![WirePart::ranged("wire", 5, 10)],
                     operation:
                         Operation::And(
                             Box::new(Operation::Or(
@@ -275,3 +282,278 @@ fn unparsed_module_causes_error_test() {
     modules("module mod() -> () {}").unwrap();
 }
 
+
+#[test]
+fn constant_folding_test() {
+    let gates = |source: &str| -> Vec<String> {
+        let (_, m) = module(source).unwrap();
+        m.instances.into_iter().map(|i| i.module).collect()
+    };
+
+    // fully constant expressions are driven by a single inverter
+    assert_eq!(gates("module M() -> (w) { w = 0b1 | 0b0; }"), vec!["Not"]);
+    assert_eq!(gates("module M(x) -> (w) { w = x & 0; }"), vec!["Not"]);
+    assert_eq!(gates("module M(x) -> (w) { w = x | 1; }"), vec!["Not"]);
+    assert_eq!(gates("module M(x) -> (w) { w = x ^ 0; }"), vec!["Buffer"]);
+    assert_eq!(gates("module M(x) -> (w) { w = x ^ 1; }"), vec!["Not"]);
+
+    // only the non-constant bits produce gates
+    assert_eq!(gates("module M(x[2], y) -> (w[2]) { w = x & {0, y}; }"), vec!["Not", "And"]);
+    assert_eq!(gates("module M(x[3]) -> (w) { w = &{x[0:1], 1}; }"), vec!["And"]);
+    assert_eq!(gates("module M(x[3]) -> (w) { w = |{x[0:1], 1}; }"), vec!["Not"]);
+    assert_eq!(gates("module M(x[2]) -> (w) { w = ^{x, 1}; }"), vec!["Xor", "Not"]);
+}
+
+#[test]
+fn common_subexpression_test() {
+    let (_, m) = module("module M(sel, a, b) -> (x, y, z) { x = !sel & a; y = b & !sel; z = (a & !sel) | b; }").unwrap();
+    let gates: Vec<_> = m.instances.iter().map(|i| i.module.as_str()).collect();
+    assert_eq!(gates, vec!["Not", "And", "And", "Or"]);
+}
+
+#[test]
+fn stdlib_test() {
+    let (rest, mods) = modules(crate::STDLIB).unwrap();
+    assert_eq!(rest, "");
+    assert!(mods.iter().any(|m| m.name == "Counter16"));
+}
+
+#[test]
+fn primitive_test() {
+    let (rest, mods) = modules("primitive Nor(b, a) -> (out);\nmodule Not(in) -> (out) {}").unwrap();
+    assert_eq!(rest, "");
+    assert!(mods[0].primitive);
+    assert_eq!(mods[0].name, "Nor");
+    assert_eq!(mods[0].locals[0].name, "b");
+    assert!(!mods[1].primitive);
+}
+
+#[test]
+fn discard_binding_test() {
+    assert_eq!(io_binding("cout = _"), Ok(("", Connection {
+        module: "cout".to_string(),
+        local: vec![],
+    })));
+}
+
+#[test]
+fn input_default_test() {
+    assert_eq!(input_wire("reset = 0"), Ok(("", Wire {
+        name: "reset".to_string(),
+        width: 1,
+        kind: WireKind::Input,
+        default: Some(vec![false]),
+        init: None,
+    })));
+    assert_eq!(input_wire("d[4] = 0x5"), Ok(("", Wire {
+        name: "d".to_string(),
+        width: 4,
+        kind: WireKind::Input,
+        default: Some(vec![true, false, true, false]),
+        init: None,
+    })));
+}
+
+#[test]
+fn bus_test() {
+    assert_eq!(input_wire("inout data[8]"), Ok(("", Wire {
+        name: "data".to_string(),
+        width: 8,
+        kind: WireKind::InOut,
+        default: None,
+        init: None,
+    })));
+    assert_eq!(input_wire("inoutdata"), Ok(("", Wire {
+        name: "inoutdata".to_string(),
+        width: 1,
+        kind: WireKind::Input,
+        default: None,
+        init: None,
+    })));
+    assert_eq!(local_bus("bus shared[4];"), Ok(("", vec![Wire {
+        name: "shared".to_string(),
+        width: 4,
+        kind: WireKind::Bus,
+        default: None,
+        init: None,
+    }])));
+    assert!(local_bus("busq;").is_err());
+}
+
+#[test]
+fn wire_declaration_test() {
+    let (_, m) = module("module M(a[4], b[4]) -> () { wire sum = a ^ b; wire any = |sum; wire both[4] = sum & a; }").unwrap();
+    let width = |name: &str| m.locals.iter().find(|w| w.name == name).unwrap().width;
+    assert_eq!(width("sum"), 4);
+    assert_eq!(width("any"), 1);
+    assert_eq!(width("both"), 4);
+
+    // declarations may use the ones that follow them
+    let (_, m) = module("module M(a[4]) -> () { wire any = |both; wire both = a & a; }").unwrap();
+    assert_eq!(m.locals.iter().find(|w| w.name == "both").unwrap().width, 4);
+
+    // explicit widths have to match the inferred ones
+    let source = "module M(a[4], b[4]) -> () {\n    wire sum[3] = a ^ b;\n}";
+    assert!(module(source).is_err());
+    let (offset, message) = declaration_error(source).unwrap();
+    assert!(source[offset..].starts_with("wire sum[3]"));
+    assert_eq!(message, "wire 'sum' is declared with 3 bits, but its operation has 4 bits");
+
+    let error = |source| declaration_error(source).map(|(_, message)| message);
+    assert_eq!(
+        error("module M(a) -> () { wire x = a & y; }"),
+        Some(String::from("wire 'x' uses the unknown wire 'y'"))
+    );
+    assert_eq!(
+        error("module M(a) -> () { wire x = a & y; wire y = x; }"),
+        Some(String::from("the width of wire 'x' depends on itself through 'y'"))
+    );
+    assert_eq!(error("module M(a) -> () { wire x = a; b = a; }"), None);
+
+    // wires whose names start with the keyword are assigned, not declared
+    let (_, m) = module("module M(a) -> (wireless) { wireless = a; }").unwrap();
+    assert_eq!(m.locals.iter().map(|w| w.name.as_str()).collect::<Vec<_>>(), vec!["a", "wireless"]);
+}
+
+#[test]
+fn wire_init_test() {
+    assert_eq!(local_wire("wire q init 1, r[4] init 0x3;"), Ok(("", vec![
+        Wire {
+            name: "q".to_string(),
+            width: 1,
+            kind: WireKind::Private,
+            default: None,
+            init: Some(vec![true]),
+        },
+        Wire {
+            name: "r".to_string(),
+            width: 4,
+            kind: WireKind::Private,
+            default: None,
+            init: Some(vec![true, true, false, false]),
+        },
+    ])));
+}
+
+#[test]
+fn register_declaration_test() {
+    let (_, m) = module("module M(clk, d[2]) -> () { reg q[2] init 0b10 = d ^ q @ clk; }").unwrap();
+    let q = m.locals.iter().find(|w| w.name == "q").unwrap();
+    assert_eq!(q.width, 2);
+    assert_eq!(q.init, Some(vec![false, true]));
+    let flip_flops = m.instances.iter().filter(|i| i.module == "DFlipFlop").count();
+    assert_eq!(flip_flops, 2);
+
+    assert!(module("module M(clk, d[2]) -> () { reg q[3] = d @ clk; }").is_err());
+}
+
+#[test]
+fn assertion_test() {
+    assert_eq!(
+        assertion("assert (q == 0) when reset;"),
+        Ok(("", Assertion {
+            condition: Operation::Eq(
+                Box::new(Operation::Wire(vec![WirePart::total("q")])),
+                Box::new(Operation::Wire(vec![WirePart::constant(vec![false])])),
+            ),
+            enable: Some(Operation::Wire(vec![WirePart::total("reset")])),
+            message: None,
+            source: "assert (q == 0) when reset;".to_string(),
+        }))
+    );
+    assert_eq!(
+        assertion("assert !(a & b) \"mutually exclusive\";"),
+        Ok(("", Assertion {
+            condition: Operation::Not(Box::new(Operation::And(
+                Box::new(Operation::Wire(vec![WirePart::total("a")])),
+                Box::new(Operation::Wire(vec![WirePart::total("b")])),
+            ))),
+            enable: None,
+            message: Some("mutually exclusive".to_string()),
+            source: "assert !(a & b) \"mutually exclusive\";".to_string(),
+        }))
+    );
+}
+
+#[test]
+fn property_test() {
+    let wire = |name| Operation::Wire(vec![WirePart::total(name)]);
+    assert_eq!(
+        property("property handshake @ clk: req |-> within 3 ack;"),
+        Ok(("", Property {
+            name: "handshake".to_string(),
+            clock: wire("clk"),
+            antecedent: Some(wire("req")),
+            consequent: Sequence::Within(3, wire("ack")),
+        }))
+    );
+    assert_eq!(
+        property("property hold @ clk: valid |-> next valid until ready;"),
+        Ok(("", Property {
+            name: "hold".to_string(),
+            clock: wire("clk"),
+            antecedent: Some(wire("valid")),
+            consequent: Sequence::Next(Box::new(Sequence::Until(wire("valid"), wire("ready")))),
+        }))
+    );
+    assert_eq!(
+        property("property never @ clk: !(a & b);"),
+        Ok(("", Property {
+            name: "never".to_string(),
+            clock: wire("clk"),
+            antecedent: None,
+            consequent: Sequence::Holds(Operation::Not(Box::new(Operation::And(
+                Box::new(wire("a")),
+                Box::new(wire("b")),
+            )))),
+        }))
+    );
+    // the clock can be a single bit of a wider wire
+    assert_eq!(
+        property("property bit @ clk[2]: a;").map(|(_, p)| p.clock),
+        Ok(Operation::Wire(vec![WirePart::ranged("clk", 2, 2)]))
+    );
+    // a wire can still be called like a keyword
+    assert_eq!(
+        property("property p @ clk: next;"),
+        Ok(("", Property {
+            name: "p".to_string(),
+            clock: wire("clk"),
+            antecedent: None,
+            consequent: Sequence::Holds(wire("next")),
+        }))
+    );
+}
+
+#[test]
+fn testbench_test() {
+    let path = |p: &str| p.split('.').map(String::from).collect::<Vec<_>>();
+    assert_eq!(
+        testbench("test \"adds\" for Adder4 {
+            set a = 3; set b = 0b100;
+            settle;
+            expect sum == 7;
+            clock reg.clk 10;
+            expect reg.q == 0x5;
+        }"),
+        Ok(("", TestBench {
+            name: "adds".to_string(),
+            module: "Adder4".to_string(),
+            steps: vec![
+                TestStep::Set(path("a"), 3),
+                TestStep::Set(path("b"), 4),
+                TestStep::Settle,
+                TestStep::Expect(path("sum"), 7),
+                TestStep::Clock(path("reg.clk"), 10),
+                TestStep::Expect(path("reg.q"), 5),
+            ],
+        }))
+    );
+
+    let (rest, parsed) = declarations("
+        module Id(a) -> (b) { b = a; }
+        test \"identity\" for Id { set a = 1; settle; expect b == 1; }
+    ").unwrap();
+    assert_eq!(rest, "");
+    assert!(matches!(parsed[..], [Declaration::Module(_), Declaration::Test(_)]));
+}
@@ -0,0 +1,128 @@
+use serde::Deserialize;
+
+use crate::lint::Level;
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// name of the manifest that is looked for in the working directory
+pub const MANIFEST: &str = "rva.toml";
+
+/// Contents of a project manifest, all paths are relative to the directory of the manifest.
+///
+/// ```toml
+/// top = "Cpu"
+/// sources = ["src"]
+/// libraries = ["../common"]
+/// exclude = ["src/old"]
+///
+/// [lint]
+/// max-fanout = 32
+/// levels = { unused-input = "allow", discarded-instance = "deny" }
+///
+/// [profiles.add]
+/// top = "Adder4"
+/// set = { a = 3, b = 0x4 }
+/// print = ["sum"]
+/// ```
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// module that is elaborated if no other is given
+    pub top: Option<String>,
+    /// directories and files with the sources of the project, the project directory if empty
+    #[serde(default)]
+    pub sources: Vec<PathBuf>,
+    /// directories with modules that can be used and replaced by the sources, earlier ones take precedence
+    #[serde(default)]
+    pub libraries: Vec<PathBuf>,
+    /// directories and files that are skipped when looking for sources
+    #[serde(default)]
+    pub exclude: Vec<PathBuf>,
+    #[serde(default)]
+    pub lint: LintSettings,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct LintSettings {
+    /// `allow`, `warn` or `deny` by the name of the lint, lints that are not listed warn
+    #[serde(default)]
+    pub levels: BTreeMap<String, Level>,
+    /// number of gate inputs a bit may drive before `excessive-fanout` warns
+    pub max_fanout: Option<usize>,
+}
+
+/// a named simulation setup
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub top: Option<String>,
+    /// wires that are set before running, by path
+    #[serde(default)]
+    pub set: BTreeMap<String, u64>,
+    /// clock that is toggled after the wires are set
+    pub clock: Option<String>,
+    pub cycles: Option<usize>,
+    /// wires and instances that are printed at the end
+    #[serde(default)]
+    pub print: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Project {
+    /// directory all paths of the manifest are relative to
+    pub root: PathBuf,
+    pub manifest: Manifest,
+}
+
+impl Project {
+    /// loads the manifest in the directory, or uses the defaults if there is none
+    pub fn load(dir: &Path) -> Result<Project, String> {
+        let path = dir.join(MANIFEST);
+        if path.is_file() {
+            Project::from_file(&path)
+        } else {
+            Ok(Project { root: dir.to_owned(), manifest: Manifest::default() })
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Project, String> {
+        let content = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let manifest = toml::from_str(&content).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let root = path.parent().map(Path::to_owned).unwrap_or_default();
+        let root = if root.as_os_str().is_empty() { PathBuf::from(".") } else { root };
+        Ok(Project { root, manifest })
+    }
+
+    pub fn top(&self) -> &str {
+        self.manifest.top.as_deref().unwrap_or("Top")
+    }
+
+    pub fn source_paths(&self) -> Vec<PathBuf> {
+        if self.manifest.sources.is_empty() {
+            vec![self.root.clone()]
+        } else {
+            self.manifest.sources.iter().map(|p| self.root.join(p)).collect()
+        }
+    }
+
+    pub fn library_paths(&self) -> Vec<PathBuf> {
+        self.manifest.libraries.iter().map(|p| self.root.join(p)).collect()
+    }
+
+    /// paths that are skipped when looking for sources, which includes the libraries
+    pub fn excluded_paths(&self) -> Vec<PathBuf> {
+        self.manifest.exclude
+            .iter()
+            .map(|p| self.root.join(p))
+            .chain(self.library_paths())
+            .collect()
+    }
+
+    pub fn profile(&self, name: &str) -> Result<&Profile, String> {
+        self.manifest.profiles.get(name).ok_or_else(|| format!("no profile with name '{}'", name))
+    }
+}
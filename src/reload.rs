@@ -0,0 +1,79 @@
+use crate::netgraph::*;
+use crate::net::*;
+
+/// what happened to the wires of a design when it was rebuilt
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ReloadReport {
+    /// number of named wires whose values were copied into the new simulation
+    pub restored: usize,
+    /// paths of wires and instances that do not exist anymore
+    pub removed: Vec<String>,
+    /// paths of wires with a different width, with the old and new width
+    pub resized: Vec<(String, usize, usize)>,
+}
+
+impl std::fmt::Display for ReloadReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Restored {} wires", self.restored)?;
+        for path in self.removed.iter() {
+            write!(f, "\nRemoved: {}", path)?;
+        }
+        for (path, old, new) in self.resized.iter() {
+            write!(f, "\nWidth changed: {} ({} -> {} bits)", path, old, new)?;
+        }
+        Ok(())
+    }
+}
+
+fn join(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", prefix, name)
+    }
+}
+
+/// names of wires and instances created for assignments, which are not reported as they change with every edit
+fn is_generated(name: &str) -> bool {
+    name.starts_with("gen_")
+}
+
+fn transfer_module(
+    old: &GraphModule, old_sim: &Simulation,
+    new: &GraphModule, new_sim: &mut Simulation,
+    prefix: &str, generated: bool, report: &mut ReloadReport)
+{
+    for wire in old.locals.iter() {
+        let path = join(prefix, &wire.name);
+        let reported = !generated && !is_generated(&wire.name);
+        match new.locals.iter().find(|w| w.name == wire.name) {
+            Some(new_wire) if new_wire.values.len() == wire.values.len() => {
+                for (&old_addr, &new_addr) in wire.values.iter().zip(new_wire.values.iter()) {
+                    new_sim.set_value(new_addr, old_sim.get_value(old_addr));
+                }
+                report.restored += reported as usize;
+            },
+            Some(new_wire) if reported => report.resized.push((path, wire.values.len(), new_wire.values.len())),
+            None if reported => report.removed.push(path),
+            _ => {},
+        }
+    }
+
+    for inst in old.instances.iter() {
+        let path = join(prefix, &inst.name);
+        let generated = generated || is_generated(&inst.name);
+        // an instance of another module with the same name does not share any state
+        match new.instances.iter().find(|i| i.name == inst.name && i.module_name == inst.module_name) {
+            Some(new_inst) => transfer_module(inst, old_sim, new_inst, new_sim, &path, generated, report),
+            None if !generated => report.removed.push(path),
+            None => {},
+        }
+    }
+}
+
+/// copies the values of all wires that exist in both designs from the old into the new simulation
+pub fn transfer(old: &GraphModule, old_sim: &Simulation, new: &GraphModule, new_sim: &mut Simulation) -> ReloadReport {
+    let mut report = ReloadReport::default();
+    transfer_module(old, old_sim, new, new_sim, "", false, &mut report);
+    report
+}
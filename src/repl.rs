@@ -0,0 +1,335 @@
+use crate::parsing::{integer, wire_path, parse_complete};
+use crate::netgraph::*;
+use crate::net::*;
+use crate::testbench;
+use crate::project::Project;
+
+use std::rc::Rc;
+
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+const COMMANDS: [&str; 11] = [
+    "get", "set", "run", "describe", "watch", "unwatch", "format", "reload", "help", "quit", "exit"
+];
+
+const HELP: &str = "\
+get <path> [format]    prints the value of a wire
+set <path> <value>     sets a wire and runs the simulation until it is stable
+run                    runs the simulation until it is stable
+describe [path]        prints the wires and instances of a module, or a wire
+watch [path]           prints the wire whenever it changes, lists all watched wires without a path
+unwatch <path>         stops watching a wire
+format [format]        sets the format values are printed in: bin, hex or dec
+reload                 rebuilds the design from the sources, keeping the values of all wires that still exist
+help                   prints this message
+quit                   exits
+
+Paths are completed with tab, values can be written in decimal, hex (0x) or binary (0b).";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Radix {
+    Bin,
+    Hex,
+    Dec,
+}
+
+impl Radix {
+    fn from_name(name: &str) -> Result<Radix, String> {
+        match name {
+            "bin" => Ok(Radix::Bin),
+            "hex" => Ok(Radix::Hex),
+            "dec" => Ok(Radix::Dec),
+            _ => Err(format!("unknown format '{}', expected bin, hex or dec", name)),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Radix::Bin => "bin",
+            Radix::Hex => "hex",
+            Radix::Dec => "dec",
+        }
+    }
+}
+
+/// formats the value of a wire, wires with floating or conflicting bits are always shown in binary
+pub fn format_value(sim: &Simulation, addr: &[usize], radix: Radix) -> String {
+    let undriven = addr.iter().any(|&a| matches!(sim.bus_state(a), Some(BusState::Floating) | Some(BusState::Contention)));
+    let bits: Vec<bool> = addr.iter().map(|&a| sim.get_value(a)).collect();
+
+    match radix {
+        Radix::Dec if !undriven && bits.len() <= 128 => {
+            let value = bits.iter().rev().fold(0u128, |acc, &b| acc << 1 | b as u128);
+            value.to_string()
+        },
+        Radix::Hex | Radix::Dec if !undriven => {
+            let digits: String = bits
+                .chunks(4)
+                .rev()
+                .map(|nibble| {
+                    let digit = nibble.iter().rev().fold(0, |acc, &b| acc << 1 | b as u32);
+                    std::char::from_digit(digit, 16).unwrap()
+                })
+                .collect();
+            format!("0x{}", digits)
+        },
+        _ => sim.display_wire(addr),
+    }
+}
+
+fn parse_path(i: &str) -> Result<Vec<String>, String> {
+    parse_complete(i, wire_path).ok_or_else(|| format!("invalid path '{}'", i))
+}
+
+struct Watch {
+    path: Vec<String>,
+    addr: Vec<usize>,
+    last: String,
+}
+
+/// interactive simulation of a design, commands are executed line by line
+pub struct Repl {
+    graph: Rc<GraphModule>,
+    sim: Simulation,
+    radix: Radix,
+    watches: Vec<Watch>,
+    /// project and top module the design is rebuilt from on reload
+    sources: Option<(Project, String)>,
+}
+
+impl Repl {
+    pub fn new(graph: GraphModule, sim: Simulation) -> Self {
+        Repl { graph: Rc::new(graph), sim, radix: Radix::Hex, watches: Vec::new(), sources: None }
+    }
+
+    pub fn set_sources(&mut self, project: Project, top: String) {
+        self.sources = Some((project, top));
+    }
+
+    pub fn graph(&self) -> Rc<GraphModule> {
+        self.graph.clone()
+    }
+
+    /// rebuilds the design and keeps watching all wires that still exist
+    fn reload(&mut self) -> Result<Vec<String>, String> {
+        let (project, top) = self.sources.as_ref().ok_or("there are no sources to reload from")?;
+        let sources = crate::load_sources(project).map_err(|e| e.to_string())?;
+        let (graph, sim, report) = crate::rebuild(sources.libraries, sources.modules, top, &self.graph, &self.sim)
+            .map_err(|e| format!("{:?}: {}", e.kind, e.description))?;
+        self.graph = Rc::new(graph);
+        self.sim = sim;
+
+        let mut lines = vec![report.to_string()];
+        let graph = &self.graph;
+        self.watches.retain_mut(|watch| match testbench::address(graph, &watch.path) {
+            Ok(addr) => {
+                watch.addr = addr;
+                true
+            },
+            Err(_) => {
+                lines.push(format!("Stopped watching '{}'", watch.path.join(".")));
+                false
+            },
+        });
+        lines.extend(self.settle());
+        Ok(lines)
+    }
+
+    fn address(&self, path: &str) -> Result<(Vec<String>, Vec<usize>), String> {
+        let path = parse_path(path)?;
+        let addr = testbench::address(&self.graph, &path)?;
+        Ok((path, addr))
+    }
+
+    /// runs the simulation until it is stable and lists failures, contentions and changed watches
+    fn settle(&mut self) -> Vec<String> {
+        let mut lines = Vec::new();
+        if let Err(e) = testbench::settle(&self.graph, &mut self.sim, &mut lines) {
+            lines.push(format!("error: {}", e));
+        }
+        for wire in self.sim.contentions() {
+            let name = self.graph.wire_path(wire).unwrap_or_else(|| wire.to_string());
+            lines.push(format!("bus contention on '{}'", name));
+        }
+        for watch in self.watches.iter_mut() {
+            let value = format_value(&self.sim, &watch.addr, self.radix);
+            if value != watch.last {
+                lines.push(format!("{}: {} -> {}", watch.path.join("."), watch.last, value));
+                watch.last = value;
+            }
+        }
+        lines
+    }
+
+    /// executes a single command and returns its output
+    pub fn execute(&mut self, line: &str) -> Result<String, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let lines = match words.as_slice() {
+            [] => Vec::new(),
+            ["get", path] => {
+                let (_, addr) = self.address(path)?;
+                vec![format_value(&self.sim, &addr, self.radix)]
+            },
+            ["get", path, radix] => {
+                let (_, addr) = self.address(path)?;
+                vec![format_value(&self.sim, &addr, Radix::from_name(radix)?)]
+            },
+            ["set", path, value] => {
+                let value = parse_complete(value, integer).ok_or_else(|| format!("invalid value '{}'", value))?;
+                testbench::set(&self.graph, &mut self.sim, &parse_path(path)?, value)?;
+                self.settle()
+            },
+            ["run"] => self.settle(),
+            ["describe"] => vec![self.graph.display(String::new(), &self.sim).trim_end().to_string()],
+            ["describe", path] => match self.graph.display_path(String::new(), &parse_path(path)?, &self.sim) {
+                Ok(description) => vec![description.trim_end().to_string()],
+                Err(Error::InvalidPath(e)) => return Err(e),
+            },
+            ["watch"] => self.watches
+                .iter()
+                .map(|w| format!("{}: {}", w.path.join("."), w.last))
+                .collect(),
+            ["watch", path] => {
+                let (path, addr) = self.address(path)?;
+                let last = format_value(&self.sim, &addr, self.radix);
+                let line = format!("{}: {}", path.join("."), last);
+                self.watches.retain(|w| w.path != path);
+                self.watches.push(Watch { path, addr, last });
+                vec![line]
+            },
+            ["unwatch", path] => {
+                let path = parse_path(path)?;
+                let count = self.watches.len();
+                self.watches.retain(|w| w.path != path);
+                if self.watches.len() == count {
+                    return Err(format!("'{}' is not being watched", path.join(".")));
+                }
+                Vec::new()
+            },
+            ["format"] => vec![self.radix.name().to_string()],
+            ["format", radix] => {
+                self.radix = Radix::from_name(radix)?;
+                for watch in self.watches.iter_mut() {
+                    watch.last = format_value(&self.sim, &watch.addr, self.radix);
+                }
+                Vec::new()
+            },
+            ["reload"] => self.reload()?,
+            ["help"] => vec![HELP.to_string()],
+            [command, ..] if COMMANDS.contains(command) => {
+                return Err(format!("wrong arguments for '{}', 'help' lists all commands", command));
+            },
+            [command, ..] => return Err(format!("unknown command '{}', 'help' lists all commands", command)),
+        };
+        Ok(lines.join("\n"))
+    }
+}
+
+/// Completes the word before `pos`, returns where the completed part starts and the candidates.
+/// The first word is a command, later words are paths through the module hierarchy.
+pub fn complete(graph: &GraphModule, line: &str, pos: usize) -> (usize, Vec<String>) {
+    let line = &line[..pos];
+    let start = line.rfind(char::is_whitespace).map(|i| i + 1).unwrap_or(0);
+    let word = &line[start..];
+
+    let names: Vec<String> = if line[..start].trim().is_empty() {
+        COMMANDS.iter().map(|c| c.to_string()).collect()
+    } else if line.starts_with("format") || (line.starts_with("get") && line[..start].split_whitespace().count() == 2) {
+        ["bin", "hex", "dec"].iter().map(|c| c.to_string()).collect()
+    } else {
+        let (prefix, _) = word.rsplit_once('.').unwrap_or(("", word));
+        let mut module = graph;
+        for name in prefix.split('.').filter(|n| !n.is_empty()) {
+            match module.instances.iter().find(|i| i.name == name) {
+                Some(inst) => module = inst,
+                None => return (pos, Vec::new()),
+            }
+        }
+        module.instances
+            .iter()
+            .map(|i| format!("{}.", i.name))
+            .chain(module.locals.iter().map(|w| w.name.clone()))
+            .collect()
+    };
+
+    let partial_start = word.rfind('.').map(|i| i + 1).unwrap_or(0);
+    let partial = &word[partial_start..];
+    let mut candidates: Vec<String> = names.into_iter().filter(|n| n.starts_with(partial)).collect();
+    candidates.sort();
+    candidates.dedup();
+    (start + partial_start, candidates)
+}
+
+struct PathHelper {
+    graph: Rc<GraphModule>,
+}
+
+impl Helper for PathHelper {}
+impl Highlighter for PathHelper {}
+impl Validator for PathHelper {}
+impl Hinter for PathHelper {
+    type Hint = String;
+}
+
+impl Completer for PathHelper {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(complete(&self.graph, line, pos))
+    }
+}
+
+fn history_file() -> Option<std::path::PathBuf> {
+    std::env::var_os("HOME").map(|home| std::path::Path::new(&home).join(".rva_history"))
+}
+
+/// reads commands from the terminal until it is closed or the user quits
+pub fn run(mut repl: Repl) -> Result<(), String> {
+    let mut editor: Editor<PathHelper, DefaultHistory> = Editor::new().map_err(|e| e.to_string())?;
+    editor.set_helper(Some(PathHelper { graph: repl.graph() }));
+    if let Some(path) = history_file() {
+        // there is no history on the first start
+        let _ = editor.load_history(&path);
+    }
+
+    for line in repl.settle() {
+        println!("{}", line);
+    }
+
+    loop {
+        let line = match editor.readline("> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.to_string()),
+        };
+        if !line.trim().is_empty() {
+            let _ = editor.add_history_entry(line.as_str());
+        }
+
+        match line.trim() {
+            "quit" | "exit" => break,
+            line => match repl.execute(line) {
+                Ok(output) if output.is_empty() => {},
+                Ok(output) => println!("{}", output),
+                Err(e) => println!("error: {}", e),
+            },
+        }
+
+        // the design changes on reload
+        if let Some(helper) = editor.helper_mut() {
+            helper.graph = repl.graph();
+        }
+    }
+
+    if let Some(path) = history_file() {
+        let _ = editor.save_history(&path);
+    }
+    Ok(())
+}
@@ -0,0 +1,145 @@
+use crate::parsed::*;
+use crate::netgraph::*;
+use crate::net::*;
+use crate::assertion::*;
+
+/// gate updates after which a simulation that is still not stable counts as oscillating
+const SETTLE_LIMIT: usize = 1_000_000;
+
+#[derive(Debug)]
+pub struct TestResult {
+    pub name: String,
+    pub module: String,
+    /// everything that went wrong, an empty list means the test passed
+    pub failures: Vec<String>,
+}
+
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+impl std::fmt::Display for TestResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let status = if self.passed() { "ok" } else { "FAILED" };
+        write!(f, "test '{}' for {} ... {}", self.name, self.module, status)?;
+        for failure in self.failures.iter() {
+            write!(f, "\n    {}", failure)?;
+        }
+        Ok(())
+    }
+}
+
+/// a wire that kept toggling while the simulation settled
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Oscillation {
+    /// hierarchical name of the bit, e.g. `ring.a[0]`
+    pub wire: String,
+    pub toggles: usize,
+    /// gate update and new value of the latest toggles, oldest first
+    pub history: Vec<(usize, bool)>,
+}
+
+impl std::fmt::Display for Oscillation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "'{}' toggled {} times, recently", self.wire, self.toggles)?;
+        for (update, value) in self.history.iter() {
+            write!(f, " {}@{}", *value as u8, update)?;
+        }
+        Ok(())
+    }
+}
+
+/// the oscillating wires of the simulation with their names in the graph
+pub fn oscillations(graph: &GraphModule, sim: &Simulation) -> Vec<Oscillation> {
+    let names = graph.wire_names();
+    sim.oscillations()
+        .into_iter()
+        .map(|t| Oscillation {
+            wire: names.get(&t.wire).cloned().unwrap_or_else(|| format!("<wire {}>", t.wire)),
+            toggles: t.count,
+            history: t.history,
+        })
+        .collect()
+}
+
+/// runs the simulation until it is stable, and collects failed assertions and properties
+pub fn settle(graph: &GraphModule, sim: &mut Simulation, failures: &mut Vec<String>) -> Result<(), String> {
+    if !sim.settle(SETTLE_LIMIT) {
+        if sim.is_oscillating() {
+            let wires: Vec<String> = oscillations(graph, sim).iter().map(|o| format!("\n    {}", o)).collect();
+            return Err(format!("simulation oscillates:{}", wires.concat()));
+        }
+        return Err(format!("simulation did not settle within {} gate updates", SETTLE_LIMIT));
+    }
+
+    failures.extend(check_assertions(graph, sim).iter().map(|f| f.to_string()));
+    failures.extend(sim.sample_monitors().iter().map(|f| f.to_string()));
+    Ok(())
+}
+
+pub fn set(graph: &GraphModule, sim: &mut Simulation, path: &[String], value: u64) -> Result<(), String> {
+    let addr = address(graph, path)?;
+    if addr.len() < 64 && value >> addr.len() != 0 {
+        return Err(format!("value {:#x} does not fit into '{}' ({} bits)", value, path.join("."), addr.len()));
+    }
+    for (idx, &a) in addr.iter().enumerate() {
+        sim.set_value(a, idx < 64 && (value >> idx) & 1 == 1);
+    }
+    Ok(())
+}
+
+pub fn address(graph: &GraphModule, path: &[String]) -> Result<Vec<usize>, String> {
+    graph.wire_addr(path).map_err(|_| format!("unknown wire '{}'", path.join(".")))
+}
+
+/// value of the wire at the path, only the lowest 64 bits are read
+pub fn get(graph: &GraphModule, sim: &Simulation, path: &[String]) -> Result<u64, String> {
+    Ok(
+        address(graph, path)?
+        .iter()
+        .take(64)
+        .enumerate()
+        .fold(0u64, |acc, (idx, &a)| acc | (sim.get_value(a) as u64) << idx)
+    )
+}
+
+fn run_steps(test: &TestBench, graph: &GraphModule, sim: &mut Simulation, failures: &mut Vec<String>) -> Result<(), String> {
+    for step in test.steps.iter() {
+        match step {
+            TestStep::Set(path, value) => set(graph, sim, path, *value)?,
+            TestStep::Settle => settle(graph, sim, failures)?,
+            TestStep::Expect(path, expected) => {
+                let value = get(graph, sim, path)?;
+                if value != *expected {
+                    failures.push(format!("expect {} == {:#x}, got {:#x}", path.join("."), expected, value));
+                }
+            },
+            TestStep::Clock(path, cycles) => {
+                // values that were set before are applied before the first edge
+                settle(graph, sim, failures)?;
+                for _ in 0..*cycles {
+                    set(graph, sim, path, 1)?;
+                    settle(graph, sim, failures)?;
+                    set(graph, sim, path, 0)?;
+                    settle(graph, sim, failures)?;
+                }
+            },
+        }
+    }
+    Ok(())
+}
+
+/// executes the steps of a test against the simulation of its module
+pub fn run(test: &TestBench, graph: &GraphModule, sim: &mut Simulation) -> TestResult {
+    let mut failures = Vec::new();
+    if let Err(e) = run_steps(test, graph, sim, &mut failures) {
+        failures.push(e);
+    }
+    TestResult {
+        name: test.name.clone(),
+        module: test.module.clone(),
+        failures,
+    }
+}
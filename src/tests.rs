@@ -0,0 +1,1123 @@
+use super::*;
+
+fn build(mods: Vec<Module>, top: &str) -> LinkResult<(GraphModule, Simulation)> {
+    build_with_libraries(Vec::new(), mods, top)
+}
+
+fn simulate_top(source: &str) -> (GraphModule, Simulation) {
+    simulate(source, "Top")
+}
+
+fn simulate(source: &str, top: &str) -> (GraphModule, Simulation) {
+    let (graph, mut sim) = build(parse(source), top).unwrap();
+    while !sim.is_stable() {
+        sim.update();
+    }
+    (graph, sim)
+}
+
+fn value(graph: &GraphModule, sim: &Simulation, path: &str) -> u64 {
+    let path: Vec<String> = path.split('.').map(String::from).collect();
+    graph.wire_addr(&path).unwrap()
+        .iter()
+        .enumerate()
+        .map(|(i, &addr)| (sim.get_value(addr) as u64) << i)
+        .sum()
+}
+
+fn set(graph: &GraphModule, sim: &mut Simulation, path: &str, value: u64) {
+    let path: Vec<String> = path.split('.').map(String::from).collect();
+    for (i, addr) in graph.wire_addr(&path).unwrap().into_iter().enumerate() {
+        sim.set_value(addr, (value >> i) & 1 > 0);
+    }
+    while !sim.is_stable() {
+        sim.update();
+    }
+}
+
+#[test]
+fn stdlib_without_sources_test() {
+    let (graph, sim) = simulate_top("
+        module Top() -> () {
+            wire sum[8], carry, selected[4], decoded[8], encoded[3], valid;
+            Adder8 add(a=0x2A, b=0x17, cin=1) -> (sum, cout=carry);
+            Mux4 mux(sel=1, a=0x3, b=0xC) -> (out=selected);
+            Decoder3 dec(in=0b101) -> (out=decoded);
+            Encoder8 enc(in=0b00101100) -> (out=encoded, valid);
+        }
+    ");
+    assert_eq!(value(&graph, &sim, "sum"), 0x2A + 0x17 + 1);
+    assert_eq!(value(&graph, &sim, "carry"), 0);
+    assert_eq!(value(&graph, &sim, "selected"), 0xC);
+    assert_eq!(value(&graph, &sim, "decoded"), 1 << 5);
+    assert_eq!(value(&graph, &sim, "encoded"), 5);
+    assert_eq!(value(&graph, &sim, "valid"), 1);
+}
+
+#[test]
+fn stdlib_override_test() {
+    // a user defined 'Not' that is actually a buffer replaces the built-in one
+    let (graph, sim) = simulate_top("
+        module Not(in) -> (out) {
+            wire inv;
+            Nor n1(a=in, b=in) -> (out=inv);
+            Nor n2(a=inv, b=inv) -> (out);
+        }
+        module Top() -> () {
+            wire w;
+            Not n(in=1) -> (out=w);
+        }
+    ");
+    assert_eq!(value(&graph, &sim, "w"), 1);
+}
+
+#[test]
+fn stdlib_counter_test() {
+    let (graph, mut sim) = simulate_top("
+        module Top() -> () {
+            wire q[4];
+            Counter4 counter(clk=0, reset=0, en=1) -> (q);
+        }
+    ");
+    let clk = graph.wire_addr(&[String::from("counter"), String::from("clk")]).unwrap()[0];
+    let reset = graph.wire_addr(&[String::from("counter"), String::from("reset")]).unwrap()[0];
+
+    let cycle = |sim: &mut Simulation, clk_value| {
+        sim.set_value(clk, clk_value);
+        while !sim.is_stable() {
+            sim.update();
+        }
+    };
+
+    sim.set_value(reset, true);
+    cycle(&mut sim, true);
+    cycle(&mut sim, false);
+    sim.set_value(reset, false);
+    cycle(&mut sim, false);
+    assert_eq!(value(&graph, &sim, "q"), 0);
+
+    for expected in 1..20 {
+        cycle(&mut sim, true);
+        cycle(&mut sim, false);
+        assert_eq!(value(&graph, &sim, "q"), expected % 16);
+    }
+}
+
+#[test]
+fn primitive_ports_by_name_test() {
+    // ports of a primitive are bound by name, not by position
+    let (graph, sim) = simulate_top("
+        primitive Nor(unused, b, a) -> (out);
+        module Top() -> () {
+            wire w;
+            Nor n(unused=1, a=0, b=0) -> (out=w);
+        }
+    ");
+    assert_eq!(value(&graph, &sim, "w"), 1);
+}
+
+#[test]
+fn undeclared_primitive_test() {
+    let error = |source| build(parse(source), "Top").err().unwrap().kind;
+
+    // leaf modules have to be declared as primitives
+    assert!(matches!(
+        error("module Nor(a, b) -> (out) {} module Top() -> () { wire w; Nor n(a=0, b=0) -> (out=w); }"),
+        ErrorKind::UndeclaredPrimitive
+    ));
+    // modules without instances and outputs are linked as they are
+    assert!(build(parse("module Top(a) -> () { }"), "Top").is_ok());
+    assert!(build(parse("module Empty(a) -> () { } module Top(a) -> () { Empty e(a) -> (); }"), "Top").is_ok());
+    assert!(matches!(
+        error("primitive Nand(a, b) -> (out); module Top() -> () { wire w; Nand n(a=0, b=0) -> (out=w); }"),
+        ErrorKind::UnknownPrimitive
+    ));
+    assert!(matches!(
+        error("primitive Nor(x, b) -> (out); module Top() -> () { wire w; Nor n(x=0, b=0) -> (out=w); }"),
+        ErrorKind::UnknownWire
+    ));
+}
+
+#[test]
+fn standalone_module_test() {
+    // the ports of the root module are primary inputs and outputs
+    let (graph, mut sim) = simulate("", "DLatch");
+    set(&graph, &mut sim, "reset", 0);
+    set(&graph, &mut sim, "d", 1);
+    set(&graph, &mut sim, "clk", 1);
+    assert_eq!(value(&graph, &sim, "q"), 1);
+    set(&graph, &mut sim, "clk", 0);
+    set(&graph, &mut sim, "d", 0);
+    assert_eq!(value(&graph, &sim, "q"), 1);
+    set(&graph, &mut sim, "clk", 1);
+    assert_eq!(value(&graph, &sim, "q"), 0);
+
+    let (graph, mut sim) = simulate("", "Adder4");
+    set(&graph, &mut sim, "a", 9);
+    set(&graph, &mut sim, "b", 8);
+    set(&graph, &mut sim, "cin", 0);
+    assert_eq!(value(&graph, &sim, "sum"), 1);
+    assert_eq!(value(&graph, &sim, "cout"), 1);
+}
+
+#[test]
+fn missing_top_module_test() {
+    let error = build(parse(""), "Top").err().unwrap();
+    assert!(matches!(error.kind, ErrorKind::UnknownModule));
+}
+
+#[test]
+fn unconnected_io_test() {
+    let (graph, sim) = simulate_top("
+        module Top() -> () {
+            wire sum[4], q;
+            Adder4 add(a=0x7, b=0x9) -> (sum, cout=_);
+            DLatch latch(clk=1, d=1) -> (q);
+        }
+    ");
+    assert_eq!(value(&graph, &sim, "sum"), 0);
+    assert_eq!(value(&graph, &sim, "add.cout"), 1);
+    assert_eq!(value(&graph, &sim, "q"), 1);
+
+    // inputs without default still need to be assigned
+    let error = build(parse("module Top() -> () { wire q; DLatch latch(clk=1) -> (q); }"), "Top");
+    assert!(matches!(error.err().unwrap().kind, ErrorKind::MissingIOWires));
+
+    // as do outputs that are not explicitly discarded
+    let error = build(parse("module Top() -> () { wire sum[4]; Adder4 add(a=0x7, b=0x9) -> (sum); }"), "Top");
+    assert!(matches!(error.err().unwrap().kind, ErrorKind::MissingIOWires));
+
+    // only outputs can be discarded
+    let error = build(parse("module Top() -> () { wire q; DLatch latch(clk=1, d=_) -> (q); }"), "Top").err().unwrap();
+    assert!(matches!(error.kind, ErrorKind::MissingIOWires));
+    assert!(error.description.contains("only outputs can be discarded"));
+
+    // defaults of the top module need the width of their input, like those of instances
+    let error = build(parse("module Top(a[4] = 0b1) -> () { }"), "Top").err().unwrap();
+    assert!(matches!(error.kind, ErrorKind::MismatchedWireSize));
+    let (graph, sim) = simulate_top("module Top(a[4] = 0b0101) -> (q[4]) { q = a; }");
+    assert_eq!(value(&graph, &sim, "q"), 5);
+}
+
+#[test]
+fn tristate_bus_test() {
+    let (graph, mut sim) = simulate("
+        module Cell(en, value[4], inout data[4]) -> () {
+            TriState4 ts(in=value, en) -> (out=data);
+        }
+        module Shared(en_a, en_b) -> (out[4]) {
+            bus data[4];
+            Cell a(en=en_a, value=0x3, data) -> ();
+            Cell b(en=en_b, value=0x5, data) -> ();
+            out = data;
+        }
+    ", "Shared");
+    let data = graph.wire_addr(&[String::from("data")]).unwrap();
+
+    assert_eq!(sim.bus_state(data[0]), Some(BusState::Floating));
+
+    set(&graph, &mut sim, "en_a", 1);
+    assert_eq!(sim.bus_state(data[0]), Some(BusState::Driven));
+    assert_eq!(value(&graph, &sim, "out"), 0x3);
+    assert!(sim.contentions().is_empty());
+
+    set(&graph, &mut sim, "en_b", 1);
+    assert_eq!(sim.contentions(), data);
+
+    set(&graph, &mut sim, "en_a", 0);
+    assert_eq!(value(&graph, &sim, "out"), 0x5);
+    assert!(sim.contentions().is_empty());
+}
+
+#[test]
+fn bus_driver_check_test() {
+    let error = |source| build(parse(source), "Top").err().unwrap().kind;
+
+    // ordinary wires still need exactly one driver
+    assert!(matches!(
+        error("module Top(a, b) -> () { wire w; TriState t0(in=a, en=b) -> (out=w); TriState t1(in=b, en=a) -> (out=w); }"),
+        ErrorKind::MultipleDrivers
+    ));
+    // buses can not be driven by ordinary gates in addition to tri-state gates
+    assert!(matches!(
+        error("module Top(a, b) -> () { bus w; TriState t0(in=a, en=b) -> (out=w); Nor n(a, b) -> (out=w); }"),
+        ErrorKind::MultipleDrivers
+    ));
+    // inout ports have to be connected to buses
+    assert!(matches!(
+        error("module Cell(inout data) -> () { TriState t(in=0, en=0) -> (out=data); } module Top() -> () { wire w; Cell c(data=w) -> (); }"),
+        ErrorKind::IncorrectWireKind
+    ));
+}
+
+#[test]
+fn power_on_state_test() {
+    let (graph, sim) = simulate("
+        module Latches() -> () {
+            wire q0 init 0, q1 init 1;
+            SRLatch sr0(s=0, r=0) -> (q=q0);
+            SRLatch sr1(s=0, r=0) -> (q=q1);
+        }
+    ", "Latches");
+    assert_eq!(value(&graph, &sim, "q0"), 0);
+    assert_eq!(value(&graph, &sim, "q1"), 1);
+
+    // a counter without reset, starting at 5
+    let (graph, mut sim) = simulate("
+        module Count(clk) -> (out[4]) {
+            wire next[4];
+            Adder4 add(a=count, b=0x1) -> (sum=next, cout=_);
+            reg count[4] init 0x5 = next @ clk;
+            out = count;
+        }
+    ", "Count");
+    assert_eq!(value(&graph, &sim, "out"), 5);
+    for expected in 6..10 {
+        set(&graph, &mut sim, "clk", 1);
+        set(&graph, &mut sim, "clk", 0);
+        assert_eq!(value(&graph, &sim, "out"), expected);
+    }
+}
+
+#[test]
+fn assertion_test() {
+    let (graph, mut sim) = simulate("
+        module Exclusive(a, b) -> () {
+            assert !(a & b) \"mutually exclusive\";
+        }
+        module Top(a, b, reset) -> (q) {
+            Exclusive ex(a, b) -> ();
+            DLatch latch(clk=a, reset, d=b) -> (q);
+            assert (q == 0) when reset;
+        }
+    ", "Top");
+    let messages = |sim: &Simulation| -> Vec<String> {
+        crate::assertion::check_assertions(&graph, sim).iter().map(|f| f.to_string()).collect()
+    };
+    assert!(messages(&sim).is_empty());
+
+    set(&graph, &mut sim, "a", 1);
+    set(&graph, &mut sim, "b", 1);
+    assert_eq!(messages(&sim), vec!["Assertion failed in 'ex' (Exclusive): mutually exclusive"]);
+
+    set(&graph, &mut sim, "a", 0);
+    set(&graph, &mut sim, "reset", 1);
+    assert_eq!(messages(&sim), vec!["Assertion failed in '<root>' (Top): assert (q == 0) when reset;"]);
+
+    let error = build(parse("module Top(a) -> () { assert a & b; }"), "Top").err().unwrap();
+    assert!(matches!(error.kind, ErrorKind::UnknownWire));
+
+    // a condition of several bits only holds if all of them are high
+    let (graph, mut sim) = simulate("
+        module Top(a[2], b[2]) -> () {
+            assert !(a & b) \"mutually exclusive\";
+        }
+    ", "Top");
+    set(&graph, &mut sim, "a", 2);
+    set(&graph, &mut sim, "b", 1);
+    assert!(crate::assertion::check_assertions(&graph, &sim).is_empty());
+    set(&graph, &mut sim, "a", 3);
+    assert_eq!(crate::assertion::check_assertions(&graph, &sim).len(), 1);
+
+    // '==' zero-extends the narrower operand in assignments as well
+    let (graph, mut sim) = simulate("
+        module Top(a[4]) -> (o, p) {
+            o = a == 0;
+            wire z = a == 0b10;
+            p = z;
+        }
+    ", "Top");
+    assert_eq!((value(&graph, &sim, "o"), value(&graph, &sim, "p")), (1, 0));
+    set(&graph, &mut sim, "a", 2);
+    assert_eq!((value(&graph, &sim, "o"), value(&graph, &sim, "p")), (0, 1));
+    set(&graph, &mut sim, "a", 6);
+    assert_eq!((value(&graph, &sim, "o"), value(&graph, &sim, "p")), (0, 0));
+}
+
+#[test]
+fn property_test() {
+    use crate::monitor::Verdict;
+
+    let (graph, mut sim) = simulate("
+        module Top(clk, req, ack, valid, ready) -> () {
+            property handshake @ clk: req |-> within 2 ack;
+            property hold @ clk: valid |-> next valid until ready;
+        }
+    ", "Top");
+    let cycle = |sim: &mut Simulation, req, ack, valid, ready| -> Vec<String> {
+        set(&graph, sim, "req", req);
+        set(&graph, sim, "ack", ack);
+        set(&graph, sim, "valid", valid);
+        set(&graph, sim, "ready", ready);
+        sim.sample_monitors();
+        set(&graph, sim, "clk", 1);
+        let failures = sim.sample_monitors().iter().map(|f| f.to_string()).collect();
+        set(&graph, sim, "clk", 0);
+        sim.sample_monitors();
+        failures
+    };
+
+    assert!(cycle(&mut sim, 1, 0, 1, 0).is_empty());
+    assert!(cycle(&mut sim, 0, 0, 1, 0).is_empty());
+    assert!(cycle(&mut sim, 0, 1, 1, 1).is_empty());
+    assert!(cycle(&mut sim, 1, 0, 0, 1).is_empty());
+    assert!(cycle(&mut sim, 0, 0, 1, 0).is_empty());
+    assert_eq!(
+        cycle(&mut sim, 0, 0, 0, 0),
+        vec!["Property 'handshake' failed in '<root>' in cycle 5", "Property 'hold' failed in '<root>' in cycle 5"]
+    );
+
+    let history = |name: &str| sim.monitors().iter().find(|m| m.property.name == name).unwrap().history.clone();
+    use Verdict::*;
+    assert_eq!(history("handshake"), vec![Idle, Idle, Pass, Idle, Idle, Fail]);
+    assert_eq!(history("hold"), vec![Idle, Idle, Pass, Pass, Idle, Fail]);
+
+    // a signal of several bits is only high if all of them are high, like assertion conditions
+    let (graph, mut sim) = simulate("
+        module Top(clk, req[2], ack) -> () {
+            property both @ clk: req |-> ack;
+        }
+    ", "Top");
+    let mut cycle = |req| {
+        set(&graph, &mut sim, "req", req);
+        sim.sample_monitors();
+        set(&graph, &mut sim, "clk", 1);
+        let failures = sim.sample_monitors().len();
+        set(&graph, &mut sim, "clk", 0);
+        failures
+    };
+    assert_eq!(cycle(1), 0);
+    assert_eq!(cycle(2), 0);
+    assert_eq!(cycle(3), 1);
+}
+
+#[test]
+fn testbench_test() {
+    let source = "
+        module Count(clk, reset) -> (q[4]) {
+            Counter4 counter(clk, reset, en=1) -> (q);
+        }
+        test \"adds\" for Adder4 {
+            set a = 3; set b = 4;
+            settle;
+            expect sum == 7;
+            set b = 0xF;
+            settle;
+            expect sum == 0x2;
+            expect cout == 1;
+        }
+        test \"counts\" for Count {
+            set reset = 1;
+            clock clk 1;
+            set reset = 0;
+            settle;
+            clock clk 5;
+            expect q == 0x5;
+            clock clk 2;
+            expect q == 0x6;
+            set q = 0x10;
+        }
+        test \"missing\" for Missing {
+            settle;
+        }
+    ";
+    let (mods, tests) = parse_files(&[(PathBuf::from("tests.rva"), source.to_string())]).unwrap();
+    let results: Vec<String> = run_tests(&[], &mods, &tests).iter().map(|r| r.to_string()).collect();
+    assert_eq!(results, vec![
+        "test 'adds' for Adder4 ... ok".to_string(),
+        "test 'counts' for Count ... FAILED\n    expect q == 0x6, got 0x7\n    value 0x10 does not fit into 'q' (4 bits)".to_string(),
+        "test 'missing' for Missing ... FAILED\n    failed to elaborate (UnknownModule): No top module with name 'Missing'.".to_string(),
+    ]);
+}
+
+#[test]
+fn parse_error_location_test() {
+    let files = vec![(
+        std::path::PathBuf::from("broken.rva"),
+        String::from("module A(a) -> (b) { b = a; }\n\nmodule B(a) -> (b) {\n    b = a &;\n}\n"),
+    )];
+    assert_eq!(parse_files(&files).err().unwrap().to_string(), "broken.rva:3: invalid declaration");
+}
+
+#[test]
+fn cli_test() {
+    use crate::cli::*;
+    let run = |args: &[&str]| cli::run(&args.iter().map(|a| a.to_string()).collect::<Vec<_>>());
+
+    assert_eq!(run(&["check", "example"]), SUCCESS);
+    assert_eq!(run(&["test", "example"]), SUCCESS);
+    assert_eq!(run(&["sim", "--top", "Adder4", "--set", "a=3", "--set", "b=0x4", "--print", "sum", "example"]), SUCCESS);
+    assert_eq!(run(&["export", "--top", "FullAdder", "--format", "dot", "example"]), SUCCESS);
+    assert_eq!(run(&["vectors", "--top", "Adder4", "--vectors", "example/adder4.vec", "example"]), SUCCESS);
+    assert_eq!(run(&["sim", "--manifest", "example/rva.toml", "--profile", "add"]), SUCCESS);
+    assert_eq!(run(&["sim", "--manifest", "example/rva.toml", "--profile", "add", "--set", "a=0x10"]), FAILURE);
+
+    assert_eq!(run(&["check", "--top", "Missing", "example"]), FAILURE);
+    assert_eq!(run(&["export", "--top", "TriState4", "--format", "blif", "example"]), FAILURE);
+
+    assert_eq!(run(&[]), USAGE_ERROR);
+    assert_eq!(run(&["bogus"]), USAGE_ERROR);
+    assert_eq!(run(&["sim", "--cycles", "3"]), USAGE_ERROR);
+    assert_eq!(run(&["check", "does/not/exist"]), USAGE_ERROR);
+    assert_eq!(run(&["vectors", "--top", "Adder4", "example"]), USAGE_ERROR);
+    assert_eq!(run(&["sim", "--manifest", "example/rva.toml", "--profile", "missing"]), USAGE_ERROR);
+    assert_eq!(run(&["check", "--manifest", "does/not/rva.toml"]), USAGE_ERROR);
+
+    assert_eq!(run(&["fmt", "--check", "example"]), SUCCESS);
+    assert_eq!(run(&["fmt", "--check", "does/not/exist"]), USAGE_ERROR);
+    assert_eq!(run(&["lint", "example"]), SUCCESS);
+    assert_eq!(run(&["lint", "--deny", "unused-module", "example"]), FAILURE);
+    assert_eq!(run(&["lint", "--allow", "unused", "example"]), USAGE_ERROR);
+
+    // paths on the command line still skip the excluded paths of a manifest in another directory
+    let dir = format!("target/rva_cli_{}", std::process::id());
+    let write = |path: &str, content: &str| {
+        let path = Path::new(&dir).join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    };
+    write("rva.toml", "exclude = [\"src/old\"]");
+    write("src/top.rva", "module Top(a) -> (b) { b = !a; }");
+    write("src/old/top.rva", "module Top(a) -> (b) {");
+    let manifest = format!("{}/rva.toml", dir);
+    assert_eq!(run(&["check", "--manifest", &manifest, &format!("{}/src", dir)]), SUCCESS);
+    assert_eq!(run(&["check", "--manifest", &manifest, &format!("./{}/src/", dir)]), SUCCESS);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn vectors_test() {
+    use crate::vectors;
+
+    let file = vectors::parse("
+        # adder
+        a, b, cin, sum, cout
+        3, 4, 0, 0x7, 0
+        0b1001, 8, 0, 2, 1
+        0xF, 0, 1, 0b0x0x, x
+    ", Some(',')).unwrap();
+    assert_eq!(file.columns.len(), 5);
+    assert_eq!(file.rows[2].values[3], vectors::Value { bits: 0b0000, mask: !0b0101, text: "0b0x0x".to_string() });
+
+    let (graph, mut sim) = simulate("", "Adder4");
+    let mismatches = vectors::run(&file, &graph, &mut sim).unwrap();
+    let report: Vec<String> = mismatches.iter().map(|m| m.to_string()).collect();
+    assert_eq!(report, vec!["line 5: sum expected 2, got 0x1"]);
+
+    // only the x digits are don't-cares, the bits above the written ones have to be zero
+    let short = vectors::parse("a b cin sum\n3 4 0 0bx1\n3 0 0 0bx1", None).unwrap();
+    let report: Vec<String> = vectors::run(&short, &graph, &mut sim).unwrap().iter().map(|m| m.to_string()).collect();
+    assert_eq!(report, vec!["line 2: sum expected 0bx1, got 0x7"]);
+
+    let dont_care_input = vectors::parse("a b cin sum\nx 0 0 0", None).unwrap();
+    assert_eq!(
+        vectors::run(&dont_care_input, &graph, &mut sim).err().unwrap(),
+        "line 2: input 'a' can not be a don't-care"
+    );
+    assert_eq!(
+        vectors::parse("a b\n1 2 3", None).err().unwrap(),
+        "line 2: expected 2 values, found 3"
+    );
+    assert_eq!(
+        vectors::run(&vectors::parse("a q\n1 2", None).unwrap(), &graph, &mut sim).err().unwrap(),
+        "unknown wire 'q'"
+    );
+}
+
+#[test]
+fn repl_test() {
+    use crate::repl::Repl;
+
+    let (graph, sim) = simulate("
+        module Top(clk, d[4]) -> (q[4]) {
+            Reg4 reg(clk, d) -> (q);
+        }
+    ", "Top");
+    let mut repl = Repl::new(graph, sim);
+    let mut run = |line: &str| repl.execute(line);
+
+    run("set reg.reset 1").unwrap();
+    run("set clk 1").unwrap();
+    run("set reg.reset 0").unwrap();
+    assert_eq!(run("watch q"), Ok("q: 0x0".to_string()));
+    run("set d 0xA").unwrap();
+    assert_eq!(run("set clk 0"), Ok(String::new()));
+    assert_eq!(run("set clk 1"), Ok("q: 0x0 -> 0xa".to_string()));
+    assert_eq!(run("get q bin"), Ok("0b1010".to_string()));
+    run("format dec").unwrap();
+    assert_eq!(run("get reg.q"), Ok("10".to_string()));
+    assert_eq!(run("watch"), Ok("q: 10".to_string()));
+    run("unwatch q").unwrap();
+
+    assert_eq!(run("get nothing"), Err("unknown wire 'nothing'".to_string()));
+    assert_eq!(run("set d 16"), Err("value 0x10 does not fit into 'd' (4 bits)".to_string()));
+    assert_eq!(run("format oct"), Err("unknown format 'oct', expected bin, hex or dec".to_string()));
+    assert_eq!(run("unwatch q"), Err("'q' is not being watched".to_string()));
+    assert_eq!(run("set d"), Err("wrong arguments for 'set', 'help' lists all commands".to_string()));
+    assert_eq!(run("jump"), Err("unknown command 'jump', 'help' lists all commands".to_string()));
+    assert_eq!(run("reload"), Err("there are no sources to reload from".to_string()));
+}
+
+#[test]
+fn repl_completion_test() {
+    use crate::repl::complete;
+
+    let (graph, _) = simulate("
+        module Top(clk, d[4]) -> (q[4]) {
+            Reg4 reg(clk, d) -> (q);
+        }
+    ", "Top");
+    let strings = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+    assert_eq!(complete(&graph, "wa", 2), (0, strings(&["watch"])));
+    assert_eq!(complete(&graph, "get ", 4), (4, strings(&["clk", "d", "q", "reg."])));
+    assert_eq!(complete(&graph, "get reg.re", 10), (8, strings(&["reg0.", "reg1.", "reset"])));
+    assert_eq!(complete(&graph, "set reg.reg0.ff1.", 17), (17, strings(&["clk", "d", "gen_0.", "invclk", "master.", "q", "reset", "slave.", "slaved"])));
+    assert_eq!(complete(&graph, "get q b", 7), (6, strings(&["bin"])));
+    assert_eq!(complete(&graph, "get missing.", 12), (12, Vec::new()));
+}
+
+#[test]
+fn reload_test() {
+    let old_source = "
+        module Top(clk, d[4], en) -> (q[4]) {
+            Reg4 reg(clk, d) -> (q);
+            wire gated[4] = 4*en & q;
+            Reg2 small(clk, d=d[0:1]) -> (q=_);
+        }
+    ";
+    let new_source = "
+        module Top(clk, d[8]) -> (q[4]) {
+            Reg4 reg(clk, d=d[0:3]) -> (q);
+        }
+    ";
+    let (graph, mut sim) = simulate(old_source, "Top");
+    set(&graph, &mut sim, "reg.reset", 1);
+    set(&graph, &mut sim, "reg.reset", 0);
+    set(&graph, &mut sim, "d", 0x9);
+    set(&graph, &mut sim, "clk", 1);
+    assert_eq!(value(&graph, &sim, "q"), 0x9);
+
+    let (graph, sim, report) = rebuild(Vec::new(), parse(new_source), "Top", &graph, &sim).unwrap();
+    assert_eq!(report.resized, vec![("d".to_string(), 4, 8)]);
+    assert_eq!(report.removed, vec!["en".to_string(), "gated".to_string(), "small".to_string()]);
+
+    // the register keeps its state, even though its input changed
+    let mut sim = sim;
+    assert!(sim.settle(1_000_000));
+    assert_eq!(value(&graph, &sim, "q"), 0x9);
+    assert_eq!(value(&graph, &sim, "clk"), 1);
+}
+
+#[test]
+fn repl_reload_test() {
+    use crate::repl::Repl;
+
+    let dir = std::env::temp_dir().join(format!("rva_reload_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("top.rva");
+    std::fs::write(&file, "module Top(a[4]) -> (b[4]) { b = a; }").unwrap();
+
+    let project = project::Project::load(&dir).unwrap();
+    let sources = load_sources(&project).unwrap();
+    let (graph, sim) = build(sources.modules, "Top").unwrap();
+    let mut repl = Repl::new(graph, sim);
+    repl.set_sources(project, "Top".to_string());
+
+    repl.execute("set a 5").unwrap();
+    repl.execute("watch b").unwrap();
+    std::fs::write(&file, "module Top(a[4]) -> (b[4], c) { b = !a; c = a[0]; }").unwrap();
+    assert_eq!(repl.execute("reload"), Ok("Restored 2 wires\nb: 0x5 -> 0xa".to_string()));
+    assert_eq!(repl.execute("get c"), Ok("0x1".to_string()));
+
+    std::fs::write(&file, "module Top(a[4]) -> (b[4]) { b = a & ; }").unwrap();
+    assert!(repl.execute("reload").unwrap_err().ends_with("top.rva:1: invalid declaration"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn project_test() {
+    use crate::project::Project;
+
+    let dir = std::env::temp_dir().join(format!("rva_project_{}", std::process::id()));
+    let write = |path: &str, content: &str| {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    };
+    write("rva.toml", "
+        top = \"Chip\"
+        sources = [\"src\"]
+        libraries = [\"lib/fast\", \"lib/slow\"]
+        exclude = [\"src/old\"]
+
+        [profiles.inc]
+        set = { a = 0x5 }
+        print = [\"b\"]
+    ");
+    write("src/chip.rva", "module Chip(a[4]) -> (b[4]) { Inc inc(a) -> (b); }");
+    write("src/old/chip.rva", "module Chip() -> () { }");
+    write("lib/fast/inc.rva", "module Inc(a[4]) -> (b[4]) { wire c; Adder4 add(a, b=0x1) -> (sum=b, cout=c); }");
+    write("lib/slow/inc.rva", "module Inc(a[4]) -> (b[4]) { b = a; }\nmodule Dec(a[4]) -> (b[4]) { b = a; }");
+
+    let project = Project::load(&dir).unwrap();
+    assert_eq!(project.top(), "Chip");
+    assert_eq!(project.profile("inc").unwrap().set.get("a"), Some(&5));
+    assert!(project.profile("missing").is_err());
+
+    // the excluded directory and the libraries are not part of the sources
+    let sources = load_sources(&project).unwrap();
+    assert_eq!(sources.files, vec![dir.join("src/chip.rva")]);
+    assert_eq!(sources.libraries.len(), 3);
+
+    // the first library defining a module is used
+    let (graph, mut sim) = build_with_libraries(sources.libraries.clone(), sources.modules.clone(), "Chip").unwrap();
+    testbench::set(&graph, &mut sim, &["a".to_string()], 5).unwrap();
+    assert!(sim.settle(1_000_000));
+    assert_eq!(testbench::get(&graph, &sim, &["b".to_string()]), Ok(6));
+
+    // modules of the sources replace the ones of the libraries
+    let mut mods = sources.modules.clone();
+    mods.append(&mut parse("module Inc(a[4]) -> (b[4]) { b = !a; }"));
+    let (graph, mut sim) = build_with_libraries(sources.libraries, mods, "Chip").unwrap();
+    testbench::set(&graph, &mut sim, &["a".to_string()], 5).unwrap();
+    assert!(sim.settle(1_000_000));
+    assert_eq!(testbench::get(&graph, &sim, &["b".to_string()]), Ok(0xA));
+
+    write("rva.toml", "top = \"Chip\"\nsource = [\"src\"]");
+    assert!(Project::load(&dir).unwrap_err().contains("unknown field"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn design_api_test() {
+    let design = Design::from_source("
+        module Acc(clk, reset, in[4]) -> (out[4]) {
+            wire sum[4], carry;
+            Adder4 add(a=out, b=in) -> (sum, cout=carry);
+            Reg4 reg(clk, reset, d=sum) -> (q=out);
+            assert !carry;
+        }
+        test \"adds\" for Adder4 {
+            set a = 1; set b = 2;
+            settle;
+            expect sum == 3;
+        }
+    ").unwrap();
+    assert_eq!(design.module_names(), vec!["Acc"]);
+    assert!(design.run_tests().iter().all(|r| r.passed()));
+
+    let mut sim = design.elaborate("Acc").unwrap();
+    assert_eq!(sim.width("reg.q").unwrap(), 4);
+    sim.set("reset", 1).unwrap();
+    sim.clock("clk", 1).unwrap();
+    sim.set("reset", 0).unwrap();
+    sim.set("in", 5).unwrap();
+    sim.clock("clk", 2).unwrap();
+    assert_eq!(sim.get("out").unwrap(), 10);
+
+    // the accumulator overflows on the second cycle and the assertion fails
+    assert!(matches!(sim.clock("clk", 2), Err(Error::Failed(f)) if !f.is_empty()));
+    assert!(matches!(sim.set("in", 0x10), Err(Error::ValueTooWide { width: 4, .. })));
+    assert!(matches!(sim.get("missing"), Err(Error::UnknownWire(_))));
+    assert!(matches!(sim.get("a..b"), Err(Error::InvalidPath(_))));
+
+    assert!(matches!(design.elaborate("Missing"), Err(Error::Link { kind: ErrorKind::UnknownModule, .. })));
+    assert!(matches!(Design::from_source("module {"), Err(Error::Parse { line: 1, .. })));
+    let error = Design::from_source("module M(a[4]) -> () {\n    wire ok = a;\n    wire x[2] = a;\n}").err().unwrap();
+    assert_eq!(error.to_string(), "<source>:3: wire 'x' is declared with 2 bits, but its operation has 4 bits");
+    assert!(matches!(Design::from_files(&["does/not/exist"]), Err(Error::Io { .. })));
+}
+
+#[test]
+fn builder_test() {
+    use crate::builder::*;
+
+    // an incrementer with a ripple carry, one stage per bit
+    let mut next = ModuleBuilder::new("Inc3");
+    next.input("a", 3).output("b", 3).wire("carry", 3);
+    next.assign_bus(vec![WirePart::ranged("carry", 0, 0)], constant(1, 1));
+    for i in 0..3 {
+        next.assign_bus(vec![WirePart::ranged("b", i, i)], slice("a", i, i) ^ slice("carry", i, i));
+        if i < 2 {
+            next.assign_bus(vec![WirePart::ranged("carry", i + 1, i + 1)], slice("a", i, i) & slice("carry", i, i));
+        }
+    }
+    next.assert(!wire("a").equals(constant(7, 3)) | wire("b").or_reduce(), Some("no wrap"));
+    let inc = next.build().unwrap();
+
+    let mut top = ModuleBuilder::new("Top");
+    top.input("a", 3).output("b", 3);
+    top.instance(Instance::new("Inc3", "inc").input("a", vec![WirePart::total("a")]).output("b", vec![WirePart::total("b")]));
+    let top = top.build().unwrap();
+
+    let mut design = Design::default();
+    design.add_module(inc.clone());
+    design.add_module(top.clone());
+    let mut sim = design.elaborate("Top").unwrap();
+    sim.set("a", 5).unwrap();
+    sim.settle().unwrap();
+    assert_eq!(sim.get("b").unwrap(), 6);
+
+    // a counter that loads the incremented value on every clock, the flip-flops start out in any state
+    let mut counter = ModuleBuilder::new("Count3");
+    counter.input("clk", 1).input_with_default("reset", 1, 0).output("q", 3).wire("count", 3).wire("next", 3);
+    counter.assign("count", wire("r") & !Operation::Wire(vec![WirePart::total("reset"); 3]));
+    counter.instance(Instance::new("Inc3", "inc").input("a", vec![WirePart::total("count")]).output("b", vec![WirePart::total("next")]));
+    counter.register("r", 3, "clk", wire("next") & !Operation::Wire(vec![WirePart::total("reset"); 3]));
+    counter.assign("q", wire("count"));
+    design.add_module(counter.build().unwrap());
+    let mut sim = design.elaborate("Count3").unwrap();
+    sim.set("reset", 1).unwrap();
+    sim.clock("clk", 1).unwrap();
+    assert_eq!(sim.get("q").unwrap(), 0);
+    sim.set("reset", 0).unwrap();
+    sim.clock("clk", 3).unwrap();
+    assert_eq!(sim.get("q").unwrap(), 3);
+
+    // the emitted source parses to the same modules
+    let source = format!("{}{}", inc, top);
+    let reparsed = Design::from_source(&source).unwrap();
+    assert_eq!(reparsed.modules(), &[inc, top]);
+    let mut sim = reparsed.elaborate("Top").unwrap();
+    sim.set("a", 7).unwrap();
+    assert!(matches!(sim.settle(), Err(Error::Failed(f)) if f[0].contains("no wrap")));
+
+    // the same holds for parsed modules with registers, buses and properties
+    let parsed = Design::from_source("
+        module Top(clk, inout data[2], sel = 0b1) -> (q[2]) {
+            bus shared[4];
+            reg x init 0b1 = q[0] @ clk;
+            reg r[2] = !{data[0], x} @ clk;
+            q = r & {sel, sel};
+            TriState4 t(in={q, q}, en=sel) -> (out=shared);
+            property stable @ clk: sel |-> next x until q[1];
+            property soon @ clk: within 2 |q;
+        }
+    ").unwrap();
+    let source: String = parsed.modules().iter().map(|m| m.to_string()).collect();
+    assert_eq!(Design::from_source(&source).unwrap().modules(), parsed.modules());
+    assert!(parsed.elaborate("Top").is_ok());
+
+    let mut broken = ModuleBuilder::new("Broken");
+    broken.input("a", 2).output("b", 1).assign("b", wire("a"));
+    assert!(matches!(broken.build(), Err(Error::InvalidModule { .. })));
+    let mut broken = ModuleBuilder::new("Broken");
+    broken.output("b", 1).assign("b", wire("missing"));
+    assert!(matches!(broken.build(), Err(Error::InvalidModule { .. })));
+}
+
+#[test]
+fn format_test() {
+    use crate::format::{self, format};
+
+    let source = "// adder\n\n\nmodule Half(a,b) -> (sum, carry) { // bits\n\
+        \tsum=a^b;   carry = a&b;\n\n\n    // reduction\n    wire w; w = &{a, b} | !b;\n}\n\
+        test \"half\" for Half {\n    set a=1; expect carry==0;\n}\n";
+    let formatted = format(source).unwrap();
+    assert_eq!(formatted, "\
+// adder
+
+module Half(a, b) -> (sum, carry) { // bits
+    sum = a ^ b;
+    carry = a & b;
+
+    // reduction
+    wire w;
+    w = &{a, b} | !b;
+}
+
+test \"half\" for Half {
+    set a = 1;
+    expect carry == 0;
+}
+");
+    assert_eq!(format(&formatted).unwrap(), formatted);
+
+    // port lists that are too long are aligned with one port per line
+    let long = format!("module Long({}) -> (out) {{\n    out = 0b0;\n}}\n", (0..12).map(|i| format!("input_{}", i)).collect::<Vec<_>>().join(", "));
+    let formatted = format(&long).unwrap();
+    assert!(formatted.lines().all(|l| l.len() <= 100));
+    assert!(formatted.contains(",\n            input_1,"));
+    assert_eq!(parse(&formatted), parse(&long));
+
+    // concatenations within a binding list are kept on one line
+    let concat = "module C(x[8], y[8], z[8]) -> (o[8]) {\n    A instance_with_long_name(a={x[0:3], y[0:3]}, \
+        b={yyyyyyy[4:7], zzz[0:3]}, c={xxxxxxxxxxx[4:7], zzz[4:7]}) -> (out=o);\n}\n";
+    assert_eq!(format(concat).unwrap(), "\
+module C(x[8], y[8], z[8]) -> (o[8]) {
+    A instance_with_long_name(a={x[0:3], y[0:3]},
+                              b={yyyyyyy[4:7], zzz[0:3]},
+                              c={xxxxxxxxxxx[4:7], zzz[4:7]}) -> (out=o);
+}
+");
+
+    // the `->` of consecutive instances stays aligned if it was aligned before
+    let aligned = "module M(a, b) -> (p, q) {\n    Buf x(in=a)      -> (out=p);\n    Buf long(in=b)   -> (out=q);\n\
+        Buf y(in=a) -> (out=p);\n    Buf z(in=b)  -> (out=q);\n}\n";
+    assert_eq!(format(aligned).unwrap(), "\
+module M(a, b) -> (p, q) {
+    Buf x(in=a)    -> (out=p);
+    Buf long(in=b) -> (out=q);
+    Buf y(in=a) -> (out=p);
+    Buf z(in=b) -> (out=q);
+}
+");
+    assert_eq!(format(crate::STDLIB).unwrap(), crate::STDLIB);
+
+    assert_eq!(format("primitive A(a) -> (b);\nmodule {\n"), Err(format::Error::Line(2, String::from("invalid declaration"))));
+}
+
+#[test]
+fn lsp_test() {
+    use crate::lsp::{self, Server};
+    use lsp_types::{Position, Url, SymbolKind};
+
+    let url = Url::parse("file:///design/top.rva").unwrap();
+    let source = "\
+module Half(a, b) -> (sum, carry) {
+    sum = a ^ b;
+    carry = a & b;
+}
+
+module Top(x[2]) -> (y[2]) {
+    wire c;
+    Half h(a=x[0], b=x[1]) -> (sum=y[0], carry=c);
+    Adder4 add(a=0000, ) -> ();
+}
+";
+    let mut server = Server::new(None);
+    server.open(url.clone(), source.to_string());
+
+    // Top is not instanced, so it is linked and the unconnected adder is reported at its name
+    let diagnostics = server.diagnostics(&url);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].range.start, Position::new(5, 7));
+
+    let definition = server.definition(&url, Position::new(7, 5)).unwrap();
+    assert_eq!(definition.range.start, Position::new(0, 7));
+    // the port in the binding leads to the port of the instanced module
+    let definition = server.definition(&url, Position::new(7, 32)).unwrap();
+    assert_eq!(definition.range.start, Position::new(0, 22));
+
+    let text = |hover: Option<lsp_types::Hover>| match hover.unwrap().contents {
+        lsp_types::HoverContents::Markup(markup) => markup.value,
+        _ => unreachable!(),
+    };
+    assert!(text(server.hover(&url, Position::new(8, 6))).contains("module Adder4(a[4], b[4], cin = 0b0) -> (sum[4], cout)"));
+    assert!(text(server.hover(&url, Position::new(5, 12))).contains("input x[2]"));
+    assert!(text(server.hover(&url, Position::new(7, 32))).contains("output sum (port of Half)"));
+
+    let labels = |items: Vec<lsp_types::CompletionItem>| items.into_iter().map(|i| i.label).collect::<Vec<_>>();
+    assert_eq!(labels(server.completion(&url, Position::new(8, 23))), ["b", "cin"]);
+    assert_eq!(labels(server.completion(&url, Position::new(8, 29))), ["sum", "cout"]);
+    assert!(server.completion(&url, Position::new(6, 9)).is_empty());
+
+    let symbols = server.symbols(&url);
+    assert_eq!(symbols.iter().map(|s| s.name.as_str()).collect::<Vec<_>>(), ["Half", "Top"]);
+    assert_eq!(symbols[1].kind, SymbolKind::MODULE);
+    let wires: Vec<_> = symbols[1].children.iter().flatten().map(|s| s.name.as_str()).collect();
+    assert_eq!(wires, ["x", "y", "c"]);
+
+    server.open(url.clone(), String::from("module Top() -> () {\n    wire;\n}\nmodule"));
+    assert_eq!(server.diagnostics(&url)[0].range.start.line, 3);
+
+    // the protocol over an in-memory connection
+    use lsp_server::{Connection, Message, Notification, Request, RequestId};
+    let (server, client) = Connection::memory();
+    let thread = std::thread::spawn(move || lsp::serve(&server));
+    let request = |id: i32, method: &str, params: serde_json::Value| {
+        client.sender.send(Message::Request(Request::new(RequestId::from(id), method.to_string(), params))).unwrap();
+        match client.receiver.recv().unwrap() {
+            Message::Response(response) => response,
+            message => panic!("unexpected {:?}", message),
+        }
+    };
+    let initialized = request(1, "initialize", serde_json::json!({ "capabilities": {} }));
+    assert!(initialized.result.unwrap()["capabilities"]["hoverProvider"].as_bool().unwrap());
+    client.sender.send(Message::Notification(Notification::new("initialized".to_string(), serde_json::json!({})))).unwrap();
+    let document = serde_json::json!({ "uri": url, "languageId": "rva", "version": 1, "text": "module Top() -> () {\n" });
+    client.sender.send(Message::Notification(Notification::new("textDocument/didOpen".to_string(), serde_json::json!({ "textDocument": document })))).unwrap();
+    match client.receiver.recv().unwrap() {
+        Message::Notification(n) => {
+            assert_eq!(n.method, "textDocument/publishDiagnostics");
+            assert_eq!(n.params["diagnostics"][0]["message"], "invalid declaration");
+        },
+        message => panic!("unexpected {:?}", message),
+    }
+    let symbols = request(2, "textDocument/documentSymbol", serde_json::json!({ "textDocument": { "uri": url } }));
+    assert_eq!(symbols.result.unwrap(), serde_json::json!([]));
+    assert!(request(3, "shutdown", serde_json::Value::Null).error.is_none());
+    client.sender.send(Message::Notification(Notification::new("exit".to_string(), serde_json::Value::Null))).unwrap();
+    thread.join().unwrap().unwrap();
+}
+
+#[test]
+fn lint_test() {
+    use crate::lint::{self, Config, Level, Lint};
+    use crate::project::LintSettings;
+
+    let modules = parse("
+        module Top(a, b, unused_in) -> (x, one, y) {
+            bus unused;
+            wire dangling;
+            wire gen_7;
+            dangling = a;
+            gen_7 = b;
+            x = a & b;
+            one = !0b0;
+            Not n(in=b) -> (out=_);
+            Buf buf(in=a) -> (out=y);
+        }
+        module Buf(in) -> (out) {
+            out = in;
+        }
+        module Spare(in) -> (out) {
+            out = in;
+        }
+        module Fan(in) -> (out[40]) {
+            out = 40*{in} & 40*{in};
+        }
+    ");
+    let tests = vec![TestBench { name: String::from("fan"), module: String::from("Fan"), steps: Vec::new() }];
+    let (graph, sim) = build(modules.clone(), "Top").unwrap();
+
+    let mut config = Config::default();
+    let warnings = lint::lint(&config, &[], &modules, &tests, &graph, &sim);
+    let found: Vec<(Lint, &str)> = warnings.iter().map(|w| (w.lint, w.message.as_str())).collect();
+    assert_eq!(found, [
+        (Lint::UnusedInput, "input 'unused_in' is never read"),
+        (Lint::UnusedWire, "wire 'unused' is never used"),
+        (Lint::UnusedWire, "wire 'dangling' is never read"),
+        (Lint::ConstantOutput, "output 'one' is only driven by constants"),
+        (Lint::DiscardedInstance, "the outputs of instance 'n' of 'Not' are all discarded"),
+        (Lint::GeneratedName, "'gen_7' may collide with the names generated for assignments"),
+        (Lint::UnusedModule, "module 'Spare' is never instanced from 'Top'"),
+    ]);
+    assert!(warnings.iter().all(|w| w.level == Level::Warn));
+    assert_eq!(warnings[1].to_string(), "warning[unused-wire]: Top: wire 'unused' is never used");
+
+    // the fanout is checked in the linked design
+    let (fan_graph, fan_sim) = build(modules.clone(), "Fan").unwrap();
+    config.max_fanout = 16;
+    let warnings = lint::lint(&config, &[], &modules, &[], &fan_graph, &fan_sim);
+    assert!(warnings.iter().any(|w| w.lint == Lint::ExcessiveFanout && w.message.starts_with("'in' drives up to")));
+
+    // every lint can be allowed or denied on its own
+    let settings: LintSettings = toml::from_str("
+        max-fanout = 100
+        levels = { unused-wire = \"allow\", unused-module = \"deny\" }
+    ").unwrap();
+    let config = Config::from_settings(&settings).unwrap();
+    assert_eq!(config.max_fanout, 100);
+    let warnings = lint::lint(&config, &[], &modules, &tests, &graph, &sim);
+    assert!(!warnings.iter().any(|w| w.lint == Lint::UnusedWire));
+    assert_eq!(warnings.iter().filter(|w| w.level == Level::Deny).count(), 1);
+    assert!(warnings.last().unwrap().to_string().starts_with("error[unused-module]"));
+
+    let settings: LintSettings = toml::from_str("levels = { unused = \"allow\" }").unwrap();
+    assert_eq!(Config::from_settings(&settings).unwrap_err(), "unknown lint 'unused'");
+    assert!(toml::from_str::<LintSettings>("levels = { unused-wire = \"never\" }").is_err());
+}
+
+#[test]
+fn loops_test() {
+    let source = "
+        module Top(x) -> (y) {
+            wire a;
+            wire b;
+            a = !b & x;
+            b = !a;
+            y = b;
+            Hold hold(x) -> ();
+            DFlipFlop ff(clk=x, d=y) -> (q=_);
+        }
+        @latch module Hold(x) -> () {
+            wire q;
+            q = !q & x;
+        }
+    ";
+    let (graph, sim) = build(parse(source), "Top").unwrap();
+    let loops = loops::find(sim.net(), &graph);
+    // the loops inside of latches are intended, the one of Top is not
+    assert_eq!(loops.len(), 1);
+    assert!(loops[0].contains(&String::from("a[0]")) && loops[0].contains(&String::from("b[0]")));
+    assert!(loops[0].iter().all(|w| !w.starts_with("hold.") && !w.starts_with("ff.")));
+
+    let (graph, sim) = build(parse(source), "Hold").unwrap();
+    assert!(loops::find(sim.net(), &graph).is_empty());
+    let design = Design::from_source(&source.replace("@latch ", "")).unwrap();
+    let loops = design.elaborate("Hold").unwrap().combinational_loops();
+    assert_eq!(loops.len(), 1);
+    assert!(loops[0].contains(&String::from("q[0]")));
+
+    // the attribute is kept by the emitter and the formatter
+    let modules = parse(source);
+    assert!(modules[1].latch && !modules[0].latch);
+    assert!(modules[1].to_string().starts_with("@latch module Hold(x) -> () {"));
+    assert_eq!(format::format("@latch   module L() -> () {}").unwrap(), "@latch module L() -> () {\n}\n");
+}
+
+#[test]
+fn oscillation_test() {
+    let source = "
+        module Ring(en) -> (y) {
+            wire a;
+            a = !a & en;
+            y = a;
+        }
+    ";
+    let (graph, mut sim) = build(parse(source), "Ring").unwrap();
+    let en = [String::from("en")];
+    assert!(sim.settle(1000) && !sim.is_oscillating());
+
+    // enabling the ring makes `a` toggle until the threshold is reached
+    testbench::set(&graph, &mut sim, &en, 1).unwrap();
+    sim.set_oscillation_threshold(50);
+    assert!(!sim.settle(usize::MAX));
+    assert!(sim.is_oscillating());
+    let oscillations = testbench::oscillations(&graph, &sim);
+    let ring = oscillations.iter().find(|o| o.wire == "a[0]").unwrap();
+    assert_eq!(ring.toggles, 50);
+    assert_eq!(ring.history.len(), 8);
+    assert!(ring.history.windows(2).all(|w| w[0].0 < w[1].0 && w[0].1 != w[1].1));
+
+    let mut failures = Vec::new();
+    let error = testbench::settle(&graph, &mut sim, &mut failures).unwrap_err();
+    assert!(error.starts_with("simulation oscillates:\n"));
+    assert!(error.contains("\n    'a[0]' toggled 50 times, recently 1@"));
+
+    // a new stimulus that stops the ring lets it settle again
+    set(&graph, &mut sim, "en", 0);
+    assert!(sim.settle(1000) && !sim.is_oscillating());
+    assert!(sim.oscillations().is_empty());
+    assert_eq!(value(&graph, &sim, "y"), 0);
+
+    // the unbounded simulation of the library stops as well
+    testbench::set(&graph, &mut sim, &en, 1).unwrap();
+    interact::set_oscillation_threshold(&mut sim, 30);
+    assert!(!unsafe { interact::simulate(&mut sim, &graph, 0) });
+    let mut ptr = std::ptr::null();
+    let len = unsafe { interact::get_oscillations(&sim, &graph, &mut ptr) };
+    let description = unsafe { String::from_utf8_lossy(std::slice::from_raw_parts(ptr, len)).into_owned() };
+    unsafe { interact::drop_chars(ptr as *mut u8, len) };
+    assert!(description.lines().any(|l| l.starts_with("'a[0]' toggled")));
+
+    let design = Design::from_source(source).unwrap();
+    let mut simulator = design.elaborate("Ring").unwrap();
+    simulator.set("en", 1).unwrap();
+    match simulator.settle() {
+        Err(Error::Oscillating(wires)) => {
+            assert!(wires.iter().any(|o| o.wire == "a[0]"));
+            assert_eq!(wires.iter().map(|o| o.toggles).max(), Some(net::OSCILLATION_THRESHOLD));
+        },
+        other => panic!("expected an oscillation, got {:?}", other),
+    }
+    let mut simulator = design.elaborate("Ring").unwrap();
+    simulator.set_oscillation_threshold(20);
+    simulator.set("en", 1).unwrap();
+    match simulator.settle() {
+        Err(Error::Oscillating(wires)) => assert_eq!(wires.iter().map(|o| o.toggles).max(), Some(20)),
+        other => panic!("expected an oscillation, got {:?}", other),
+    }
+}
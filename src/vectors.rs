@@ -0,0 +1,156 @@
+use crate::parsed::WireKind;
+use crate::parsing::{integer, wire_path, parse_complete};
+use crate::netgraph::*;
+use crate::net::*;
+use crate::testbench;
+
+/// a value in a vector file, bits outside of the mask are don't-cares
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Value {
+    pub bits: u64,
+    pub mask: u64,
+    /// the value as written in the file
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Row {
+    pub line: usize,
+    pub values: Vec<Value>,
+}
+
+/// Columns of wires with one row per step. Columns of inputs of the top module are
+/// applied to the simulation, all other columns are expected values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VectorFile {
+    pub columns: Vec<Vec<String>>,
+    pub rows: Vec<Row>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    pub line: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+fn parse_value(text: &str) -> Option<Value> {
+    let text = text.to_string();
+    if text == "x" || text == "X" || text == "-" {
+        return Some(Value { bits: 0, mask: 0, text });
+    }
+
+    if let Some(digits) = text.strip_prefix("0b") {
+        if digits.is_empty() || digits.len() > 64 {
+            return None;
+        }
+        let (mut bits, mut mask) = (0, 0);
+        for c in digits.chars() {
+            bits <<= 1;
+            mask <<= 1;
+            match c {
+                '0' => mask |= 1,
+                '1' => { bits |= 1; mask |= 1 },
+                'x' | 'X' | '-' => {},
+                _ => return None,
+            }
+        }
+        // the bits above the written digits are zero, not don't-cares
+        if digits.len() < 64 {
+            mask |= u64::MAX << digits.len();
+        }
+        return Some(Value { bits, mask, text });
+    }
+
+    parse_complete(&text, integer).map(|bits| Value { bits, mask: u64::MAX, text })
+}
+
+/// Parses a vector file, fields are separated by `separator` or by whitespace if there is none.
+/// The first line names the wires of the columns, empty lines and lines starting with '#' are ignored.
+pub fn parse(source: &str, separator: Option<char>) -> Result<VectorFile, String> {
+    let split = |line: &str| -> Vec<String> {
+        match separator {
+            Some(sep) => line.split(sep).map(|f| f.trim().to_string()).collect(),
+            None => line.split_whitespace().map(String::from).collect(),
+        }
+    };
+
+    let mut lines = source
+        .lines()
+        .enumerate()
+        .map(|(idx, line)| (idx + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+    let columns = match lines.next() {
+        Some((line, header)) => split(header)
+            .iter()
+            .map(|name| parse_complete(name, wire_path).ok_or_else(|| format!("line {}: invalid path '{}'", line, name)))
+            .collect::<Result<Vec<_>, _>>()?,
+        None => return Err(String::from("the file has no header")),
+    };
+
+    let mut rows = Vec::new();
+    for (line, text) in lines {
+        let fields = split(text);
+        if fields.len() != columns.len() {
+            return Err(format!("line {}: expected {} values, found {}", line, columns.len(), fields.len()));
+        }
+        let values = fields
+            .iter()
+            .map(|field| parse_value(field).ok_or_else(|| format!("line {}: invalid value '{}'", line, field)))
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.push(Row { line, values });
+    }
+
+    Ok(VectorFile { columns, rows })
+}
+
+fn is_input(graph: &GraphModule, path: &[String]) -> bool {
+    path.len() == 1 && graph.locals.iter().any(|w| w.name == path[0] && w.kind == WireKind::Input)
+}
+
+/// Applies every row to the simulation, settles it and compares the expected values.
+/// Returns all mismatches, or an error if the file does not fit the design.
+pub fn run(file: &VectorFile, graph: &GraphModule, sim: &mut Simulation) -> Result<Vec<Mismatch>, String> {
+    for path in file.columns.iter() {
+        testbench::address(graph, path)?;
+    }
+
+    let mut mismatches = Vec::new();
+    for row in file.rows.iter() {
+        let error = |e| format!("line {}: {}", row.line, e);
+
+        for (path, value) in file.columns.iter().zip(row.values.iter()) {
+            if !is_input(graph, path) {
+                continue;
+            }
+            if value.mask != u64::MAX {
+                return Err(error(format!("input '{}' can not be a don't-care", path.join("."))));
+            }
+            testbench::set(graph, sim, path, value.bits).map_err(error)?;
+        }
+
+        let mut failures = Vec::new();
+        testbench::settle(graph, sim, &mut failures).map_err(error)?;
+        mismatches.extend(failures.into_iter().map(|message| Mismatch { line: row.line, message }));
+
+        for (path, value) in file.columns.iter().zip(row.values.iter()) {
+            if is_input(graph, path) {
+                continue;
+            }
+            let actual = testbench::get(graph, sim, path)?;
+            if actual & value.mask != value.bits & value.mask {
+                mismatches.push(Mismatch {
+                    line: row.line,
+                    message: format!("{} expected {}, got {:#x}", path.join("."), value.text, actual),
+                });
+            }
+        }
+    }
+    Ok(mismatches)
+}